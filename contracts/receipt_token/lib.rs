@@ -0,0 +1,154 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+#![feature(min_specialization)]
+
+/// A PSP-22 receipt token minting a share per token staked in a staking contract. Transferring
+/// shares moves the stake behind them.
+#[openbrush::contract]
+pub mod token {
+    use openbrush::{
+        contracts::{access_control::*, psp22::extensions::metadata::*},
+        modifiers,
+        traits::{self, Storage},
+    };
+    use staking_dapp::traits::{receipt::*, staking::StakingRef};
+
+    const MANAGER: RoleType = ink::selector_id!("MANAGER");
+    const MINTER: RoleType = ink::selector_id!("MINTER");
+
+    #[ink(storage)]
+    #[derive(Default, Storage)]
+    pub struct ReceiptTokenContract {
+        #[storage_field]
+        psp22: psp22::Data,
+        #[storage_field]
+        metadata: metadata::Data,
+        #[storage_field]
+        access: access_control::Data,
+        staking_contract: AccountId,
+    }
+
+    impl PSP22 for ReceiptTokenContract {}
+
+    impl PSP22Metadata for ReceiptTokenContract {}
+
+    impl AccessControl for ReceiptTokenContract {}
+
+    impl psp22::Transfer for ReceiptTokenContract {
+        fn _before_token_transfer(
+            &mut self,
+            from: Option<&AccountId>,
+            to: Option<&AccountId>,
+            amount: &Balance,
+        ) -> Result<(), PSP22Error> {
+            // Mints and burns follow the stake, transfers between holders move it.
+            let (Some(from), Some(to)) = (from, to) else {
+                return Ok(());
+            };
+
+            StakingRef::transfer_stake(&self.staking_contract, *from, *to, *amount)
+                .map_err(|_| PSP22Error::Custom(traits::String::from("StakeNotTransferable")))
+        }
+    }
+
+    impl Receipt for ReceiptTokenContract {
+        #[ink(message)]
+        #[modifiers(only_role(MINTER))]
+        fn mint(&mut self, account: AccountId, amount: Balance) -> Result<(), PSP22Error> {
+            self._mint_to(account, amount)
+        }
+
+        #[ink(message)]
+        #[modifiers(only_role(MINTER))]
+        fn burn(&mut self, account: AccountId, amount: Balance) -> Result<(), PSP22Error> {
+            self._burn_from(account, amount)
+        }
+
+        #[ink(message)]
+        fn staking_contract(&self) -> AccountId {
+            self.staking_contract
+        }
+    }
+
+    impl ReceiptTokenContract {
+        /// Creates a new `ReceiptTokenContract` instance with the given `name`, `symbol` and
+        /// `decimals`, which should match the staking token.
+        #[ink(constructor)]
+        pub fn new(
+            name: Option<traits::String>,
+            symbol: Option<traits::String>,
+            decimals: u8,
+        ) -> Self {
+            let mut instance = Self::default();
+            instance._init_with_admin(instance.env().caller());
+            instance
+                .grant_role(MANAGER, instance.env().caller())
+                .expect("Should grant MANAGER role");
+
+            instance.metadata.name = name;
+            instance.metadata.symbol = symbol;
+            instance.metadata.decimals = decimals;
+            instance
+        }
+
+        /// Sets the staking contract the shares are staked in, and allows it to mint and burn
+        /// them. The staking contract must then set this contract as its receipt token.
+        #[ink(message)]
+        #[modifiers(only_role(MANAGER))]
+        pub fn set_staking_contract(
+            &mut self,
+            staking_contract: AccountId,
+        ) -> Result<(), PSP22Error> {
+            if self.has_role(MINTER, self.staking_contract) {
+                self.revoke_role(MINTER, self.staking_contract)?;
+            }
+            self.grant_role(MINTER, staking_contract)?;
+            self.staking_contract = staking_contract;
+            Ok(())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use openbrush::test_utils::*;
+
+        fn new_token() -> ReceiptTokenContract {
+            ReceiptTokenContract::new(
+                Some(traits::String::from("Staked My Staking Token")),
+                Some(traits::String::from("stMST")),
+                18,
+            )
+        }
+
+        #[ink::test]
+        fn only_the_staking_contract_mints_and_burns_shares() {
+            let mut token = new_token();
+            let staking_contract = AccountId::from([0x7; 32]);
+            let bob = accounts().bob;
+
+            token.set_staking_contract(staking_contract).unwrap();
+            assert_eq!(token.staking_contract(), staking_contract);
+            assert!(token.mint(bob, 100).is_err());
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(staking_contract);
+            assert_eq!(token.mint(bob, 100), Ok(()));
+            assert_eq!(token.burn(bob, 40), Ok(()));
+
+            assert_eq!(token.balance_of(bob), 60);
+            assert_eq!(token.total_supply(), 60);
+        }
+
+        #[ink::test]
+        fn changing_the_staking_contract_revokes_the_previous_one() {
+            let mut token = new_token();
+            let old = AccountId::from([0x7; 32]);
+            let new = AccountId::from([0x8; 32]);
+
+            token.set_staking_contract(old).unwrap();
+            token.set_staking_contract(new).unwrap();
+
+            assert!(!token.has_role(MINTER, old));
+            assert!(token.has_role(MINTER, new));
+        }
+    }
+}
@@ -16,44 +16,94 @@ pub mod token {
         reputation: Mapping<AccountId, u128>,
     }
 
+    /// Emitted when an account reaches a new reputation level.
+    #[ink(event)]
+    pub struct LevelReached {
+        #[ink(topic)]
+        account: AccountId,
+        level: u32,
+    }
+
     const MANAGER: RoleType = ink::selector_id!("MANAGER");
     const MINTER: RoleType = ink::selector_id!("MINTER");
 
+    /// Progress towards the next level is reported in basis points.
+    const MAX_PROGRESS: u16 = 10_000;
+
     impl PSP37 for ReputationTokenContract {}
 
     impl Reputation for ReputationTokenContract {
         #[ink(message)]
         #[modifiers(only_role(MINTER))]
+        fn add_reputation(&mut self, account: AccountId, delta: u128) -> Result<(), PSP37Error> {
+            let reputation = self.reputation.get(&account).unwrap_or(0);
+            self.set_reputation(account, reputation.saturating_add(delta))
+        }
+
+        #[ink(message)]
+        #[modifiers(only_role(MANAGER))]
         fn update_reputation(
             &mut self,
             account: AccountId,
             new_reputation: u128,
         ) -> Result<(), PSP37Error> {
-            let level = Self::get_level(self.reputation.get(&account).unwrap_or(0)) as u32;
-            let new_level = Self::get_level(new_reputation);
+            self.set_reputation(account, new_reputation)
+        }
 
-            for i in level..new_level {
-                assert!(self
-                    ._mint_to(account, [(Id::U32(i + 1), 1u128)].to_vec())
-                    .is_ok());
-            }
-            self.reputation.insert(&account, &new_reputation);
-            Ok(())
+        #[ink(message)]
+        fn reputation_of(&self, account: AccountId) -> u128 {
+            self.reputation.get(&account).unwrap_or(0)
+        }
+
+        #[ink(message)]
+        fn level_of(&self, account: AccountId) -> u32 {
+            Self::get_level(self.reputation_of(account))
+        }
+
+        #[ink(message)]
+        fn next_level_threshold(&self, account: AccountId) -> Option<u128> {
+            Self::level_threshold(self.level_of(account) + 1)
+        }
+
+        #[ink(message)]
+        fn progress_to_next_level(&self, account: AccountId) -> u16 {
+            let reputation = self.reputation_of(account);
+            let level = Self::get_level(reputation);
+            let (Some(current), Some(next)) = (
+                Self::level_threshold(level),
+                Self::level_threshold(level + 1),
+            ) else {
+                return MAX_PROGRESS;
+            };
+
+            // Thresholds are multiples of 1e9, so the span between two levels splits exactly into
+            // basis points, and scaling it down rather than the reputation up cannot overflow.
+            ((reputation - current) / ((next - current) / MAX_PROGRESS as u128)) as u16
         }
     }
 
     impl Internal for ReputationTokenContract {
         fn get_level(reputation: u128) -> u32 {
             let mut level = 0;
-            let mut threshold = 1_000_000_000;
 
-            while reputation >= threshold {
+            while let Some(threshold) = Self::level_threshold(level + 1) {
+                if reputation < threshold {
+                    break;
+                }
                 level += 1;
-                threshold *= 10;
             }
 
             level
         }
+
+        fn level_threshold(level: u32) -> Option<u128> {
+            match level {
+                0 => Some(0),
+                _ => 10u128
+                    .checked_pow(level - 1)
+                    .and_then(|factor| factor.checked_mul(1_000_000_000)),
+            }
+        }
     }
 
     impl ReputationTokenContract {
@@ -74,6 +124,31 @@ pub mod token {
             self.grant_role(MINTER, account)?;
             Ok(())
         }
+
+        /// Stores the new reputation of the account and mints or burns one token per level so
+        /// that the account holds exactly the levels its reputation reaches.
+        fn set_reputation(
+            &mut self,
+            account: AccountId,
+            reputation: u128,
+        ) -> Result<(), PSP37Error> {
+            let level = Self::get_level(self.reputation.get(&account).unwrap_or(0));
+            let new_level = Self::get_level(reputation);
+
+            for i in level..new_level {
+                self._mint_to(account, [(Id::U32(i + 1), 1u128)].to_vec())?;
+                self.env().emit_event(LevelReached {
+                    account,
+                    level: i + 1,
+                });
+            }
+            for i in new_level..level {
+                self._burn_from(account, [(Id::U32(i + 1), 1u128)].to_vec())?;
+            }
+
+            self.reputation.insert(&account, &reputation);
+            Ok(())
+        }
     }
 
     #[cfg(test)]
@@ -112,5 +187,100 @@ pub mod token {
             let result = contract.update_reputation(bob, 10_000_000_000);
             assert!(result.is_err());
         }
+
+        #[ink::test]
+        fn add_reputation_accumulates() {
+            let mut contract = ReputationTokenContract::new();
+            let alice = AccountId::from([0x1; 32]);
+            let bob = AccountId::from([0x2; 32]);
+
+            contract.set_minter(alice).unwrap();
+            for _ in 0..10 {
+                contract.add_reputation(bob, 1_000_000_000).unwrap();
+            }
+
+            assert_eq!(contract.reputation_of(bob), 10_000_000_000);
+            assert_eq!(contract.level_of(bob), 2);
+            assert_eq!(contract.balance_of(bob, Some(Id::U32(1))), 1);
+            assert_eq!(contract.balance_of(bob, Some(Id::U32(2))), 1);
+        }
+
+        #[ink::test]
+        fn progress_is_measured_between_level_thresholds() {
+            let mut contract = ReputationTokenContract::new();
+            let bob = AccountId::from([0x2; 32]);
+
+            assert_eq!(contract.next_level_threshold(bob), Some(1_000_000_000));
+            assert_eq!(contract.progress_to_next_level(bob), 0);
+
+            contract.update_reputation(bob, 500_000_000).unwrap();
+            assert_eq!(contract.progress_to_next_level(bob), 5_000);
+
+            contract.update_reputation(bob, 3_250_000_000).unwrap();
+            assert_eq!(contract.next_level_threshold(bob), Some(10_000_000_000));
+            assert_eq!(contract.progress_to_next_level(bob), 2_500);
+        }
+
+        #[ink::test]
+        fn the_highest_level_does_not_overflow() {
+            let mut contract = ReputationTokenContract::new();
+            let bob = AccountId::from([0x2; 32]);
+
+            contract.update_reputation(bob, u128::MAX).unwrap();
+
+            assert_eq!(contract.level_of(bob), 30);
+            assert_eq!(contract.next_level_threshold(bob), None);
+            assert_eq!(contract.progress_to_next_level(bob), 10_000);
+        }
+
+        #[ink::test]
+        fn reaching_a_level_emits_an_event() {
+            type Event = <ReputationTokenContract as ink::reflect::ContractEventBase>::Type;
+
+            let mut contract = ReputationTokenContract::new();
+            let bob = AccountId::from([0x2; 32]);
+
+            contract.update_reputation(bob, 10_000_000_000).unwrap();
+            contract.update_reputation(bob, 1_000_000_000).unwrap();
+
+            let levels: Vec<_> = ink::env::test::recorded_events()
+                .map(|event| {
+                    assert_eq!(event.topics.len(), 2);
+                    match <Event as scale::Decode>::decode(&mut &event.data[..])
+                        .expect("encountered invalid contract event data buffer")
+                    {
+                        Event::LevelReached(event) => (event.account, event.level),
+                    }
+                })
+                .collect();
+            assert_eq!(levels, vec![(bob, 1), (bob, 2)]);
+        }
+
+        #[ink::test]
+        fn update_reputation_burns_levels_above_the_new_reputation() {
+            let mut contract = ReputationTokenContract::new();
+            let bob = AccountId::from([0x2; 32]);
+
+            contract.update_reputation(bob, 10_000_000_000).unwrap();
+            contract.update_reputation(bob, 1_000_000_000).unwrap();
+
+            assert_eq!(contract.level_of(bob), 1);
+            assert_eq!(contract.balance_of(bob, Some(Id::U32(1))), 1);
+            assert_eq!(contract.balance_of(bob, Some(Id::U32(2))), 0);
+        }
+
+        #[ink::test]
+        fn only_managers_can_overwrite_reputation() {
+            let mut contract = ReputationTokenContract::new();
+            let bob = AccountId::from([0x2; 32]);
+            let charlie = AccountId::from([0x3; 32]);
+
+            contract.set_minter(bob).unwrap();
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(bob);
+
+            assert!(contract.add_reputation(charlie, 1_000_000_000).is_ok());
+            assert!(contract.update_reputation(charlie, 0).is_err());
+            assert_eq!(contract.reputation_of(charlie), 1_000_000_000);
+        }
     }
 }
@@ -3,16 +3,224 @@
 
 #[openbrush::contract]
 pub mod staking {
-    use openbrush::traits::{DefaultEnv, Storage};
-    use staking_dapp::traits::reputation::ReputationRef;
+    use ink::prelude::vec::Vec;
+    use openbrush::{
+        contracts::{access_control::*, pausable::*, traits::psp22::PSP22Ref},
+        modifiers,
+        traits::{DefaultEnv, Storage},
+    };
+    use staking_dapp::traits::{receipt::ReceiptRef, reputation::ReputationRef};
     use staking_dapp::{
-        impls::{reputation, staking},
-        traits::staking::*,
+        impls::{
+            reputation,
+            staking::{
+                self,
+                data::FEE_SCALE,
+                emission,
+                extra_rewards::{RewardState, MAX_REWARD_TOKENS},
+                math,
+                position::{Position, PositionId},
+                vesting::PENALTY_SCALE,
+            },
+        },
+        traits::staking::{Internal, *},
     };
 
-    const HALVING_PERIOD: Timestamp = 60 * 60 * 24 * 365;
-    const INITIAL_REWARD_RATE: u128 = 50;
-    const REPUTATION_PERIOD: Timestamp = 60 * 60 * 24;
+    /// Staking one token for this long earns one reputation point. Block timestamps are in
+    /// milliseconds.
+    const REPUTATION_PERIOD: Timestamp = 24 * 60 * 60 * 1000;
+
+    const ADMIN: RoleType = DEFAULT_ADMIN_ROLE;
+    const PAUSER: RoleType = ink::selector_id!("PAUSER");
+    const REWARD_MANAGER: RoleType = ink::selector_id!("REWARD_MANAGER");
+    const REWARD_DISTRIBUTOR: RoleType = ink::selector_id!("REWARD_DISTRIBUTOR");
+
+    /// Emitted when tokens are staked in a position.
+    #[ink(event)]
+    pub struct Staked {
+        #[ink(topic)]
+        staker: AccountId,
+        position: PositionId,
+        amount: Balance,
+    }
+
+    /// Emitted when tokens are withdrawn from a position, either to their staker or into an
+    /// unstake request.
+    #[ink(event)]
+    pub struct Withdrawn {
+        #[ink(topic)]
+        staker: AccountId,
+        position: PositionId,
+        amount: Balance,
+    }
+
+    /// Emitted when a staker requests to unstake tokens, which can be withdrawn from
+    /// `release_time` on.
+    #[ink(event)]
+    pub struct UnstakeRequested {
+        #[ink(topic)]
+        staker: AccountId,
+        amount: Balance,
+        release_time: Timestamp,
+    }
+
+    /// Emitted when the tokens of completed unstake requests are transferred to their staker.
+    #[ink(event)]
+    pub struct UnstakeCompleted {
+        #[ink(topic)]
+        staker: AccountId,
+        amount: Balance,
+    }
+
+    /// Emitted when staking rewards are paid out.
+    #[ink(event)]
+    pub struct RewardPaid {
+        #[ink(topic)]
+        staker: AccountId,
+        reward: Balance,
+    }
+
+    /// Emitted when rewards in an extra reward token are paid out.
+    #[ink(event)]
+    pub struct ExtraRewardPaid {
+        #[ink(topic)]
+        staker: AccountId,
+        #[ink(topic)]
+        token: AccountId,
+        reward: Balance,
+    }
+
+    /// Emitted when a staker sets the address their rewards are paid to, `None` when they are
+    /// paid to the staker again.
+    #[ink(event)]
+    pub struct RewardRecipientChanged {
+        #[ink(topic)]
+        staker: AccountId,
+        recipient: Option<AccountId>,
+    }
+
+    /// Emitted when a staker authorizes an operator to claim their rewards, `None` when they
+    /// revoke it.
+    #[ink(event)]
+    pub struct OperatorChanged {
+        #[ink(topic)]
+        staker: AccountId,
+        operator: Option<AccountId>,
+    }
+
+    /// Emitted when a token is added to the extra reward tokens.
+    #[ink(event)]
+    pub struct RewardTokenAdded {
+        #[ink(topic)]
+        token: AccountId,
+    }
+
+    /// Emitted when rewards are added to the distribution of an extra reward token, which
+    /// releases `reward_rate` tokens per millisecond until `period_finish`.
+    #[ink(event)]
+    pub struct RewardAdded {
+        #[ink(topic)]
+        token: AccountId,
+        amount: Balance,
+        reward_rate: Balance,
+        period_finish: Timestamp,
+    }
+
+    /// Emitted when claimed rewards start vesting, until `end` along with the rewards already
+    /// vesting.
+    #[ink(event)]
+    pub struct RewardVested {
+        #[ink(topic)]
+        staker: AccountId,
+        amount: Balance,
+        end: Timestamp,
+    }
+
+    /// Emitted when a staker forfeits part of their vesting rewards by releasing them early.
+    #[ink(event)]
+    pub struct EarlyExitPenalty {
+        #[ink(topic)]
+        staker: AccountId,
+        penalty: Balance,
+    }
+
+    /// Emitted when `fee` tokens withdrawn by `staker` are sent to the treasury, or kept for the
+    /// emission pool.
+    #[ink(event)]
+    pub struct WithdrawalFeeCharged {
+        #[ink(topic)]
+        staker: AccountId,
+        fee: Balance,
+    }
+
+    /// Emitted when the withdrawal fee changes.
+    #[ink(event)]
+    pub struct WithdrawalFeeChanged {
+        withdrawal_fee: u16,
+        withdrawal_fee_period: Timestamp,
+    }
+
+    /// Emitted when the minimum stake or the stake caps change.
+    #[ink(event)]
+    pub struct StakeLimitsChanged {
+        min_stake: Balance,
+        max_stake_per_account: Option<Balance>,
+        max_total_staked: Option<Balance>,
+    }
+
+    /// Emitted when the vesting of claimed rewards changes.
+    #[ink(event)]
+    pub struct VestingChanged {
+        vesting_period: Timestamp,
+        early_exit_penalty: u16,
+    }
+
+    /// Emitted when the address withdrawal fees and forfeited rewards are sent to changes.
+    #[ink(event)]
+    pub struct TreasuryChanged {
+        treasury: Option<AccountId>,
+    }
+
+    /// Emitted when the emission schedule restarts. `era_emission` is the amount released until
+    /// the next halving, `halving_period` later. The halvings themselves emit nothing.
+    #[ink(event)]
+    pub struct RewardRateChanged {
+        era_emission: Balance,
+        halving_period: Timestamp,
+    }
+
+    /// Emitted when whole reputation points are minted to a staker.
+    #[ink(event)]
+    pub struct ReputationAccrued {
+        #[ink(topic)]
+        staker: AccountId,
+        reputation: u128,
+    }
+
+    /// Emitted when the halving period of the emission schedule changes.
+    #[ink(event)]
+    pub struct HalvingPeriodChanged {
+        halving_period: Timestamp,
+    }
+
+    /// Emitted when the unbonding period changes.
+    #[ink(event)]
+    pub struct UnbondingPeriodChanged {
+        unbonding_period: Timestamp,
+    }
+
+    /// Emitted when the reputation token contract changes.
+    #[ink(event)]
+    pub struct ReputationTokenChanged {
+        #[ink(topic)]
+        reputation_token: AccountId,
+    }
+
+    /// Emitted when the receipt token contract changes, `None` when receipts are disabled.
+    #[ink(event)]
+    pub struct ReceiptTokenChanged {
+        receipt_token: Option<AccountId>,
+    }
 
     #[ink(storage)]
     #[derive(Storage)]
@@ -21,187 +229,2442 @@ pub mod staking {
         staking: staking::data::Data,
         #[storage_field]
         reputation: reputation::data::Data,
+        #[storage_field]
+        access: access_control::Data,
+        #[storage_field]
+        pause: pausable::Data,
     }
 
     impl Internal for StakingContract {
         fn reward_per_token(&self) -> Result<Balance, StakingError> {
-            if self.staking.total_staked == 0 {
+            if self.staking.total_weighted == 0 {
                 return Ok(self.staking.reward_per_token_stored);
             }
 
-            let time_since_last_update = self
-                .last_time_reward_applicable()?
-                .checked_sub(self.staking.last_update_time)
-                .ok_or(StakingError::OverflowError)?;
-
-            let halving_duration = time_since_last_update
-                .checked_div(HALVING_PERIOD)
-                .ok_or(StakingError::DivideByZero)?;
+            let pool = self.staking.emission_pool;
+            let genesis = self.staking.emission_start;
+            let period = self.staking.halving_period;
 
-            let current_reward_rate = self
-                .staking
-                .reward_rate
-                .checked_div(
-                    2u128
-                        .checked_pow(
-                            halving_duration
-                                .try_into()
-                                .map_err(|_| StakingError::OverflowError)?,
-                        )
-                        .ok_or(StakingError::OverflowError)?,
-                )
-                .ok_or(StakingError::DivideByZero)?;
+            let emitted =
+                emission::emitted(pool, genesis, period, self.last_time_reward_applicable()?)?
+                    .checked_sub(emission::emitted(
+                        pool,
+                        genesis,
+                        period,
+                        self.staking.last_update_time,
+                    )?)
+                    .ok_or(StakingError::OverflowError)?;
 
             self.staking
                 .reward_per_token_stored
                 .checked_add(
-                    (time_since_last_update as u128)
-                        .checked_mul(current_reward_rate)
-                        .ok_or(StakingError::OverflowError)?
-                        .checked_mul(10u128.pow(18))
-                        .ok_or(StakingError::OverflowError)?
-                        .checked_div(self.staking.total_staked)
-                        .ok_or(StakingError::DivideByZero)?,
+                    math::mul_div(emitted, 10u128.pow(18), self.staking.total_weighted)
+                        .ok_or(StakingError::OverflowError)?,
                 )
                 .ok_or(StakingError::OverflowError)
         }
 
+        fn update_reward_per_token(&mut self) -> Result<(), StakingError> {
+            // With nobody staked the emission is held back, and the first stakers share it.
+            if self.staking.total_weighted > 0 {
+                self.staking.reward_per_token_stored = Internal::reward_per_token(self)?;
+                self.staking.last_update_time = self.last_time_reward_applicable()?;
+            }
+
+            let now = Self::env().block_timestamp();
+            for token in self.staking.reward_tokens.clone() {
+                let mut state = self.staking.reward_states.get(&token).unwrap_or_default();
+                state.update(self.staking.total_weighted, now)?;
+                self.staking.reward_states.insert(&token, &state);
+            }
+            Ok(())
+        }
+
         fn update_reward(&mut self, staker: AccountId) -> Result<(), StakingError> {
-            self.staking.reward_per_token_stored = self.reward_per_token()?;
-            self.staking.last_update_time = self.last_time_reward_applicable()?;
-            self.staking.rewards.insert(&staker, &self.earned(staker)?);
-            self.staking
-                .user_reward_per_token_paid
-                .insert(&staker, &self.staking.reward_per_token_stored);
+            for id in self.staking.positions_of(&staker) {
+                self.update_position_reward(id)?;
+            }
             Ok(())
         }
 
+        fn update_position_reward(&mut self, id: PositionId) -> Result<Position, StakingError> {
+            self.update_reward_per_token()?;
+
+            let mut position = self
+                .staking
+                .positions
+                .get(&id)
+                .ok_or(StakingError::PositionNotFound)?;
+            // The weight of the position is about to change, and with it the weight of its owner.
+            self.update_extra_rewards(position.owner)?;
+            position.rewards = self.position_earned(&position)?;
+            position.reward_per_token_paid = self.staking.reward_per_token_stored;
+
+            self.staking.positions.insert(&id, &position);
+            Ok(position)
+        }
+
         fn earned(&self, staker: AccountId) -> Result<Balance, StakingError> {
-            let staked_amount = self.staking.balances.get(&staker).unwrap_or(0);
-
-            staked_amount
-                .checked_mul(
-                    self.reward_per_token()?
-                        .checked_sub(
-                            self.staking
-                                .user_reward_per_token_paid
-                                .get(&staker)
-                                .unwrap_or(0),
-                        )
-                        .ok_or(StakingError::OverflowError)?,
-                )
+            self.staking
+                .positions_of(&staker)
+                .iter()
+                .filter_map(|id| self.staking.positions.get(id))
+                .try_fold(0, |earned: Balance, position| {
+                    earned
+                        .checked_add(self.position_earned(&position)?)
+                        .ok_or(StakingError::OverflowError)
+                })
+        }
+
+        fn position_earned(&self, position: &Position) -> Result<Balance, StakingError> {
+            let reward_per_token_delta = Internal::reward_per_token(self)?
+                .checked_sub(position.reward_per_token_paid)
+                .ok_or(StakingError::OverflowError)?;
+
+            math::mul_div(position.weight()?, reward_per_token_delta, 10u128.pow(18))
                 .ok_or(StakingError::OverflowError)?
-                .checked_add(self.staking.rewards.get(&staker).unwrap_or(0))
+                .checked_add(position.rewards)
                 .ok_or(StakingError::OverflowError)
         }
 
         fn last_time_reward_applicable(&self) -> Result<Timestamp, StakingError> {
-            let now = Self::env().block_timestamp();
+            // The halving schedule never runs dry, so rewards accrue up to the current block.
+            Ok(Self::env().block_timestamp())
+        }
 
-            if now
-                < self
+        fn update_extra_rewards(&mut self, staker: AccountId) -> Result<(), StakingError> {
+            for token in self.staking.reward_tokens.clone() {
+                let earned = self.extra_earned(token, staker)?;
+                let reward_per_token_stored = self
                     .staking
-                    .last_update_time
-                    .checked_add(HALVING_PERIOD)
-                    .ok_or(StakingError::OverflowError)?
-            {
-                Ok(now)
-            } else {
+                    .reward_states
+                    .get(&token)
+                    .unwrap_or_default()
+                    .reward_per_token_stored;
+
+                self.staking.user_rewards.insert(&(token, staker), &earned);
                 self.staking
-                    .last_update_time
-                    .checked_add(HALVING_PERIOD)
-                    .ok_or(StakingError::OverflowError)
+                    .user_reward_per_token_paid
+                    .insert(&(token, staker), &reward_per_token_stored);
             }
+            Ok(())
+        }
+
+        fn extra_earned(
+            &self,
+            token: AccountId,
+            staker: AccountId,
+        ) -> Result<Balance, StakingError> {
+            let reward_per_token = self
+                .staking
+                .reward_states
+                .get(&token)
+                .ok_or(StakingError::UnknownRewardToken)?
+                .reward_per_token(self.staking.total_weighted, Self::env().block_timestamp())?;
+
+            let reward_per_token_delta = reward_per_token
+                .checked_sub(
+                    self.staking
+                        .user_reward_per_token_paid
+                        .get(&(token, staker))
+                        .unwrap_or(0),
+                )
+                .ok_or(StakingError::OverflowError)?;
+
+            math::mul_div(
+                self.staking.weight_of(&staker)?,
+                reward_per_token_delta,
+                10u128.pow(18),
+            )
+            .ok_or(StakingError::OverflowError)?
+            .checked_add(self.staking.user_rewards.get(&(token, staker)).unwrap_or(0))
+            .ok_or(StakingError::OverflowError)
+        }
+
+        fn checkpoint_reputation(&mut self, staker: AccountId) -> Result<(), StakingError> {
+            let accrued = self.accrued_reputation(staker)?;
+
+            self.reputation
+                .reputation_last_update
+                .insert(&staker, &Self::env().block_timestamp());
+            self.reputation.reputation_accrued.insert(&staker, &accrued);
+            Ok(())
         }
 
         fn update_reputation(&mut self, staker: AccountId) -> Result<(), StakingError> {
+            self.checkpoint_reputation(staker)?;
+
+            let accrued = self.reputation.reputation_accrued.get(&staker).unwrap_or(0);
+            let new_reputation = accrued / 10u128.pow(18);
+
+            if new_reputation > 0 {
+                self.reputation
+                    .reputation_accrued
+                    .insert(&staker, &(accrued % 10u128.pow(18)));
+
+                ReputationRef::add_reputation(
+                    &self.reputation.reputation_token,
+                    staker,
+                    new_reputation,
+                )?;
+
+                self.env().emit_event(ReputationAccrued {
+                    staker,
+                    reputation: new_reputation,
+                });
+            }
+            Ok(())
+        }
+
+        fn mint_receipt(&mut self, staker: AccountId, amount: Balance) -> Result<(), StakingError> {
+            if let Some(receipt_token) = self.staking.receipt_token {
+                ReceiptRef::mint(&receipt_token, staker, amount)?;
+            }
+            Ok(())
+        }
+
+        fn burn_receipt(&mut self, staker: AccountId, amount: Balance) -> Result<(), StakingError> {
+            if let Some(receipt_token) = self.staking.receipt_token {
+                ReceiptRef::burn(&receipt_token, staker, amount)?;
+            }
+            Ok(())
+        }
+
+        fn emit_staked_event(&self, staker: AccountId, position: PositionId, amount: Balance) {
+            self.env().emit_event(Staked {
+                staker,
+                position,
+                amount,
+            });
+        }
+
+        fn emit_withdrawn_event(&self, staker: AccountId, position: PositionId, amount: Balance) {
+            self.env().emit_event(Withdrawn {
+                staker,
+                position,
+                amount,
+            });
+        }
+
+        fn emit_reward_paid_event(&self, staker: AccountId, reward: Balance) {
+            self.env().emit_event(RewardPaid { staker, reward });
+        }
+
+        fn emit_extra_reward_paid_event(
+            &self,
+            staker: AccountId,
+            token: AccountId,
+            reward: Balance,
+        ) {
+            self.env().emit_event(ExtraRewardPaid {
+                staker,
+                token,
+                reward,
+            });
+        }
+
+        fn emit_reward_recipient_changed_event(
+            &self,
+            staker: AccountId,
+            recipient: Option<AccountId>,
+        ) {
+            self.env()
+                .emit_event(RewardRecipientChanged { staker, recipient });
+        }
+
+        fn emit_operator_changed_event(&self, staker: AccountId, operator: Option<AccountId>) {
+            self.env().emit_event(OperatorChanged { staker, operator });
+        }
+
+        fn emit_reward_vested_event(&self, staker: AccountId, amount: Balance, end: Timestamp) {
+            self.env().emit_event(RewardVested {
+                staker,
+                amount,
+                end,
+            });
+        }
+
+        fn emit_early_exit_penalty_event(&self, staker: AccountId, penalty: Balance) {
+            self.env().emit_event(EarlyExitPenalty { staker, penalty });
+        }
+
+        fn emit_withdrawal_fee_event(&self, staker: AccountId, fee: Balance) {
+            self.env().emit_event(WithdrawalFeeCharged { staker, fee });
+        }
+
+        fn emit_unstake_requested_event(
+            &self,
+            staker: AccountId,
+            amount: Balance,
+            release_time: Timestamp,
+        ) {
+            self.env().emit_event(UnstakeRequested {
+                staker,
+                amount,
+                release_time,
+            });
+        }
+
+        fn emit_unstake_completed_event(&self, staker: AccountId, amount: Balance) {
+            self.env().emit_event(UnstakeCompleted { staker, amount });
+        }
+
+        fn schedule_rewards(&mut self, amount: Balance) -> Result<(), StakingError> {
             let now = Self::env().block_timestamp();
+            self.update_reward_per_token()?;
+
+            // Halvings are memoryless: restarting the schedule with what is left releases it at
+            // the same pace as the current schedule would at an anniversary. What was emitted
+            // while nobody was staked has not been shared out yet, so it is left in the pool.
+            let pool = self.staking.emission_pool;
+            let remaining = pool
+                .checked_sub(emission::emitted(
+                    pool,
+                    self.staking.emission_start,
+                    self.staking.halving_period,
+                    self.staking.last_update_time,
+                )?)
+                .ok_or(StakingError::OverflowError)?;
+
+            self.staking.emission_pool = remaining
+                .checked_add(amount)
+                .and_then(|pool| pool.checked_add(self.staking.undistributed_rewards))
+                .ok_or(StakingError::OverflowError)?;
+            self.staking.undistributed_rewards = 0;
+            self.staking.emission_start = now;
+            self.staking.last_update_time = now;
+            Ok(())
+        }
+    }
+
+    impl Staking for StakingContract {}
+
+    impl AccessControl for StakingContract {}
+
+    impl Pausable for StakingContract {}
+
+    impl StakingContract {
+        #[ink(constructor)]
+        pub fn new(
+            staking_token: AccountId,
+            reward_token: AccountId,
+            reputation_token: AccountId,
+        ) -> Self {
+            let mut instance = StakingContract {
+                staking: Default::default(),
+                reputation: Default::default(),
+                access: Default::default(),
+                pause: Default::default(),
+            };
+
+            let caller = instance.env().caller();
+            instance._init_with_admin(caller);
+            instance
+                .grant_role(PAUSER, caller)
+                .expect("Should grant PAUSER role");
+            instance
+                .grant_role(REWARD_MANAGER, caller)
+                .expect("Should grant REWARD_MANAGER role");
+            instance
+                .grant_role(REWARD_DISTRIBUTOR, caller)
+                .expect("Should grant REWARD_DISTRIBUTOR role");
+
+            instance.staking.staking_token = staking_token;
+            instance.staking.reward_token = reward_token;
+            instance.staking.last_update_time = instance.env().block_timestamp();
+            instance.reputation.reputation_token = reputation_token;
+            instance
+        }
+
+        /// Transfers `amount` reward tokens from the caller to the contract and adds them to the
+        /// emission pool, along with the withdrawal fees and forfeited rewards kept since the last
+        /// funding without a treasury. The halving schedule restarts at the current block: half
+        /// of the pool is released during the next year, a quarter during the year after, and so
+        /// on.
+        ///
+        /// The caller must have approved the contract to transfer the specified amount of tokens
+        /// and hold the `REWARD_DISTRIBUTOR` role.
+        #[ink(message)]
+        #[modifiers(only_role(REWARD_DISTRIBUTOR))]
+        pub fn fund_rewards(&mut self, amount: Balance) -> Result<(), StakingError> {
+            if amount == 0 {
+                return Err(StakingError::ZeroAmount);
+            }
+
+            PSP22Ref::transfer_from(
+                &self.staking.reward_token,
+                self.env().caller(),
+                self.env().account_id(),
+                amount,
+                Vec::<u8>::new(),
+            )?;
+
+            self.staking.reward_reserve = self
+                .staking
+                .reward_reserve
+                .checked_add(amount)
+                .ok_or(StakingError::OverflowError)?;
+            self.schedule_rewards(amount)?;
+
+            self.emit_reward_rate_changed_event();
+            Ok(())
+        }
+
+        /// Adds `token` to the extra reward tokens. Its rewards are shared out by weight like the
+        /// rewards of the emission schedule, once they are funded with `notify_reward_amount`.
+        ///
+        /// The caller must hold the `REWARD_MANAGER` role.
+        ///
+        /// Returns `StakingError::RewardTokenAlreadyAdded` if the token is the reward token or
+        /// already an extra reward token.
+        /// Returns `StakingError::TooManyRewardTokens` if `MAX_REWARD_TOKENS` tokens are already
+        /// distributed.
+        #[ink(message)]
+        #[modifiers(only_role(REWARD_MANAGER))]
+        pub fn add_reward_token(&mut self, token: AccountId) -> Result<(), StakingError> {
+            if token == self.staking.reward_token || self.staking.reward_tokens.contains(&token) {
+                return Err(StakingError::RewardTokenAlreadyAdded);
+            }
+            if self.staking.reward_tokens.len() >= MAX_REWARD_TOKENS {
+                return Err(StakingError::TooManyRewardTokens);
+            }
+
+            self.staking.reward_tokens.push(token);
+            self.staking.reward_states.insert(
+                &token,
+                &RewardState {
+                    last_update_time: self.env().block_timestamp(),
+                    ..Default::default()
+                },
+            );
+
+            self.env().emit_event(RewardTokenAdded { token });
+            Ok(())
+        }
+
+        /// Transfers `amount` tokens of the extra reward token `token` from the caller to the
+        /// contract and releases them linearly over `duration`, along with what the current
+        /// distribution of the token has not released yet.
+        ///
+        /// The caller must have approved the contract to transfer the specified amount of tokens
+        /// and hold the `REWARD_DISTRIBUTOR` role.
+        ///
+        /// Returns `StakingError::ZeroAmount` if `amount` or `duration` is zero.
+        /// Returns `StakingError::UnknownRewardToken` if the token is not an extra reward token.
+        #[ink(message)]
+        #[modifiers(only_role(REWARD_DISTRIBUTOR))]
+        pub fn notify_reward_amount(
+            &mut self,
+            token: AccountId,
+            amount: Balance,
+            duration: Timestamp,
+        ) -> Result<(), StakingError> {
+            if amount == 0 || duration == 0 {
+                return Err(StakingError::ZeroAmount);
+            }
+            if !self.staking.reward_tokens.contains(&token) {
+                return Err(StakingError::UnknownRewardToken);
+            }
+
+            PSP22Ref::transfer_from(
+                &token,
+                self.env().caller(),
+                self.env().account_id(),
+                amount,
+                Vec::<u8>::new(),
+            )?;
+
+            let state = self.add_extra_rewards(token, amount, duration)?;
+
+            self.env().emit_event(RewardAdded {
+                token,
+                amount,
+                reward_rate: state.reward_rate,
+                period_finish: state.period_finish,
+            });
+            Ok(())
+        }
+
+        /// Pauses staking, withdrawals and reward claims. Stakers can still leave with
+        /// `emergency_withdraw`, forfeiting the pending rewards and reputation of the positions
+        /// they leave.
+        ///
+        /// The caller must hold the `PAUSER` role.
+        #[ink(message)]
+        #[modifiers(only_role(PAUSER))]
+        pub fn pause(&mut self) -> Result<(), StakingError> {
+            self._pause()
+        }
+
+        /// Resumes staking, withdrawals and reward claims.
+        ///
+        /// The caller must hold the `PAUSER` role.
+        #[ink(message)]
+        #[modifiers(only_role(PAUSER))]
+        pub fn unpause(&mut self) -> Result<(), StakingError> {
+            self._unpause()
+        }
+
+        /// Sets how long an emission era lasts before the emission rate halves. What the current
+        /// schedule has not released yet is rescheduled from the current block.
+        ///
+        /// The caller must hold the `REWARD_MANAGER` role.
+        #[ink(message)]
+        #[modifiers(only_role(REWARD_MANAGER))]
+        pub fn set_halving_period(
+            &mut self,
+            halving_period: Timestamp,
+        ) -> Result<(), StakingError> {
+            if halving_period == 0 {
+                return Err(StakingError::ZeroAmount);
+            }
+
+            self.schedule_rewards(0)?;
+            self.staking.halving_period = halving_period;
+
+            self.env()
+                .emit_event(HalvingPeriodChanged { halving_period });
+            self.emit_reward_rate_changed_event();
+            Ok(())
+        }
+
+        /// Sets how long claimed rewards vest before they can be released, and the share of the
+        /// unvested rewards forfeited when releasing them early, in basis points. A zero period
+        /// pays the rewards right away, and a zero penalty disables early releases. The penalty
+        /// is sent to the treasury, or kept for the emission pool without one. The rewards
+        /// already vesting keep their schedule.
+        ///
+        /// The caller must hold the `REWARD_MANAGER` role.
+        ///
+        /// Returns `StakingError::BasisPointsOutOfRange` if the penalty exceeds 10_000.
+        #[ink(message)]
+        #[modifiers(only_role(REWARD_MANAGER))]
+        pub fn set_vesting(
+            &mut self,
+            vesting_period: Timestamp,
+            early_exit_penalty: u16,
+        ) -> Result<(), StakingError> {
+            if early_exit_penalty > PENALTY_SCALE {
+                return Err(StakingError::BasisPointsOutOfRange);
+            }
+
+            self.staking.vesting_period = vesting_period;
+            self.staking.early_exit_penalty = early_exit_penalty;
+
+            self.env().emit_event(VestingChanged {
+                vesting_period,
+                early_exit_penalty,
+            });
+            Ok(())
+        }
+
+        /// Sets the share of the tokens withdrawn less than `withdrawal_fee_period` after they
+        /// were deposited charged as a fee, in basis points. A zero fee disables it. The fees are
+        /// sent to the treasury, or kept for the emission pool without one, in which case they
+        /// are only charged when the rewards are paid in the staking token. Compounded rewards
+        /// and canceled unstake requests are not charged again.
+        ///
+        /// The caller must hold the `ADMIN` role.
+        ///
+        /// Returns `StakingError::BasisPointsOutOfRange` if the fee exceeds 10_000.
+        #[ink(message)]
+        #[modifiers(only_role(ADMIN))]
+        pub fn set_withdrawal_fee(
+            &mut self,
+            withdrawal_fee: u16,
+            withdrawal_fee_period: Timestamp,
+        ) -> Result<(), StakingError> {
+            if withdrawal_fee > FEE_SCALE {
+                return Err(StakingError::BasisPointsOutOfRange);
+            }
+
+            self.staking.withdrawal_fee = withdrawal_fee;
+            self.staking.withdrawal_fee_period = withdrawal_fee_period;
+
+            self.env().emit_event(WithdrawalFeeChanged {
+                withdrawal_fee,
+                withdrawal_fee_period,
+            });
+            Ok(())
+        }
+
+        /// Sets the address the withdrawal fees and the penalties of early vesting releases are
+        /// sent to. Without one, they are kept in the reserve and added to the emission pool by
+        /// the next `fund_rewards`.
+        ///
+        /// The caller must hold the `ADMIN` role.
+        #[ink(message)]
+        #[modifiers(only_role(ADMIN))]
+        pub fn set_treasury(&mut self, treasury: Option<AccountId>) -> Result<(), StakingError> {
+            self.staking.treasury = treasury;
+
+            self.env().emit_event(TreasuryChanged { treasury });
+            Ok(())
+        }
+
+        /// Sets the smallest amount that can be staked at once, and caps the stake of each account
+        /// and the total stake, `None` lifting a cap. The limits apply to the tokens staked with
+        /// `stake`, `stake_locked` and `stake_for`, and the account cap to the stake moved with the
+        /// receipt token. Compounded rewards and cancelled unstake requests are not limited, and
+        /// the stakes already above a new cap are kept.
+        ///
+        /// The caller must hold the `ADMIN` role.
+        #[ink(message)]
+        #[modifiers(only_role(ADMIN))]
+        pub fn set_stake_limits(
+            &mut self,
+            min_stake: Balance,
+            max_stake_per_account: Option<Balance>,
+            max_total_staked: Option<Balance>,
+        ) -> Result<(), StakingError> {
+            self.staking.min_stake = min_stake;
+            self.staking.max_stake_per_account = max_stake_per_account;
+            self.staking.max_total_staked = max_total_staked;
+
+            self.env().emit_event(StakeLimitsChanged {
+                min_stake,
+                max_stake_per_account,
+                max_total_staked,
+            });
+            Ok(())
+        }
+
+        /// Sets how long unstaked tokens wait before they can be withdrawn. Once it is set, tokens
+        /// are unstaked with `request_unstake` instead of `withdraw`, and the pending requests
+        /// keep the release time they were made with.
+        ///
+        /// The caller must hold the `ADMIN` role.
+        #[ink(message)]
+        #[modifiers(only_role(ADMIN))]
+        pub fn set_unbonding_period(
+            &mut self,
+            unbonding_period: Timestamp,
+        ) -> Result<(), StakingError> {
+            self.staking.unbonding_period = unbonding_period;
+
+            self.env()
+                .emit_event(UnbondingPeriodChanged { unbonding_period });
+            Ok(())
+        }
+
+        /// Sets the reputation token contract the staking contract mints reputation on. The
+        /// staking contract must hold the `MINTER` role on it.
+        ///
+        /// The caller must hold the `ADMIN` role.
+        #[ink(message)]
+        #[modifiers(only_role(ADMIN))]
+        pub fn set_reputation_token(
+            &mut self,
+            reputation_token: AccountId,
+        ) -> Result<(), StakingError> {
+            self.reputation.reputation_token = reputation_token;
+
+            self.env()
+                .emit_event(ReputationTokenChanged { reputation_token });
+            Ok(())
+        }
+
+        /// Sets the receipt token contract minting a share per staked token, or disables receipts
+        /// with `None`. The staking contract must be the staking contract of the receipt token.
+        /// Shares are only minted for the tokens staked afterwards, so it can only change while no
+        /// tokens are staked or unbonding.
+        ///
+        /// The caller must hold the `ADMIN` role.
+        ///
+        /// Returns `StakingError::StakeOutstanding` if tokens are staked or unbonding.
+        #[ink(message)]
+        #[modifiers(only_role(ADMIN))]
+        pub fn set_receipt_token(
+            &mut self,
+            receipt_token: Option<AccountId>,
+        ) -> Result<(), StakingError> {
+            if self.staking.total_staked > 0 || self.staking.total_unbonding > 0 {
+                return Err(StakingError::StakeOutstanding);
+            }
+
+            self.staking.receipt_token = receipt_token;
+
+            self.env().emit_event(ReceiptTokenChanged { receipt_token });
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn claim_reputation(&mut self) -> Result<(), StakingError> {
+            self.update_reputation(self.env().caller())?;
+            Ok(())
+        }
+
+        /// Returns the reputation points the caller would be minted by `claim_reputation` at the
+        /// current block.
+        #[ink(message)]
+        pub fn pending_reputation(&self, account: AccountId) -> Result<u128, StakingError> {
+            Ok(self.accrued_reputation(account)? / 10u128.pow(18))
+        }
+
+        /// Returns the reputation accrued by the staker up to the current block and not minted
+        /// yet, times 1e18.
+        fn accrued_reputation(&self, staker: AccountId) -> Result<u128, StakingError> {
             let last_time_update = self
                 .reputation
                 .reputation_last_update
                 .get(&staker)
                 .unwrap_or(0);
 
-            let time_elapsed = now
-                .checked_sub(last_time_update)
-                .ok_or(StakingError::OverflowError)?;
+            let time_elapsed = Self::env()
+                .block_timestamp()
+                .checked_sub(last_time_update)
+                .ok_or(StakingError::OverflowError)?;
+
+            let balance = self.staking.balances.get(&staker).unwrap_or(0);
+
+            // Token-time is accrued at full precision and only whole points are minted, so the
+            // fraction of a point earned between two updates is carried over instead of dropped.
+            math::mul_div(balance, time_elapsed as u128, REPUTATION_PERIOD as u128)
+                .ok_or(StakingError::OverflowError)?
+                .checked_add(self.reputation.reputation_accrued.get(&staker).unwrap_or(0))
+                .ok_or(StakingError::OverflowError)
+        }
+
+        /// Adds `amount` to the distribution of the extra reward token over `duration`, and
+        /// returns the new distribution.
+        fn add_extra_rewards(
+            &mut self,
+            token: AccountId,
+            amount: Balance,
+            duration: Timestamp,
+        ) -> Result<RewardState, StakingError> {
+            self.update_reward_per_token()?;
+
+            let mut state = self
+                .staking
+                .reward_states
+                .get(&token)
+                .ok_or(StakingError::UnknownRewardToken)?;
+            state.notify(amount, duration, self.env().block_timestamp())?;
+
+            self.staking.reward_states.insert(&token, &state);
+            Ok(state)
+        }
+
+        fn emit_reward_rate_changed_event(&self) {
+            self.env().emit_event(RewardRateChanged {
+                era_emission: emission::era_emission(self.staking.emission_pool, 0),
+                halving_period: self.staking.halving_period,
+            });
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use ink::codegen::Env;
+        use staking_dapp::impls::staking::{
+            lockup, position::MAX_POSITIONS, unbonding::Unlock, vesting::Vesting,
+        };
+        use staking_token::token::StakingTokenContract;
+
+        const INITIAL_SUPPLY: Balance = 1_000_000_000 * 10u128.pow(18);
+
+        #[ink::test]
+        fn instantiation() {
+            let name = Some(openbrush::traits::String::from("My Staking Token"));
+            let symbol = Some(openbrush::traits::String::from("MST"));
+            let staking_token =
+                StakingTokenContract::new(name.clone(), symbol.clone(), 18, INITIAL_SUPPLY);
+
+            let reputation_token = AccountId::from([0x1; 32]);
+
+            let staking_contract = StakingContract::new(
+                staking_token.env().account_id(),
+                staking_token.env().account_id(),
+                reputation_token,
+            );
+            assert_eq!(staking_contract.staking.total_staked, 0);
+            assert_eq!(staking_contract.staking.emission_pool, 0);
+            assert_eq!(staking_contract.staking.reward_per_token_stored, 0);
+            assert_eq!(staking_contract.staking.last_update_time, 0);
+            assert_eq!(
+                staking_contract.staking.staking_token,
+                staking_token.env().account_id()
+            );
+            assert_eq!(
+                staking_contract.staking.reward_token,
+                staking_token.env().account_id()
+            );
+        }
+
+        fn set_time(timestamp: Timestamp) {
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(timestamp);
+        }
+
+        /// Returns a contract whose emission started at genesis with the given pool.
+        fn new_contract_with_pool(pool: Balance) -> StakingContract {
+            set_time(0);
+            let mut contract = StakingContract::new(
+                AccountId::from([0x1; 32]),
+                AccountId::from([0x1; 32]),
+                AccountId::from([0x2; 32]),
+            );
+            contract.staking.emission_pool = pool;
+            contract
+        }
+
+        /// Returns a contract emitting 50 tokens per millisecond during the first era.
+        fn new_contract() -> StakingContract {
+            new_contract_with_pool(2 * 50 * emission::ERA_DURATION as Balance)
+        }
+
+        // The off-chain environment cannot execute the PSP22 and PSP37 cross-contract calls, so
+        // the helpers below run the bookkeeping `stake`, `withdraw` and `get_reward` share with
+        // the helpers of the staking implementation, without the transfers around it.
+        fn stake(contract: &mut StakingContract, staker: AccountId, amount: Balance) -> PositionId {
+            stake_locked(contract, staker, amount, 0)
+        }
+
+        fn stake_locked(
+            contract: &mut StakingContract,
+            staker: AccountId,
+            amount: Balance,
+            duration: Timestamp,
+        ) -> PositionId {
+            staking::staking::add_position(contract, staker, amount, duration).unwrap()
+        }
+
+        /// Credits `amount` tokens to the beneficiary, like `stake_for`.
+        fn stake_for(contract: &mut StakingContract, beneficiary: AccountId, amount: Balance) {
+            contract
+                .staking
+                .ensure_stake_limits(&beneficiary, amount)
+                .unwrap();
+            staking::staking::credit_stake(contract, beneficiary, amount, true).unwrap();
+        }
+
+        /// Withdraws from the unlocked positions of the staker, newest first.
+        fn withdraw(contract: &mut StakingContract, staker: AccountId, amount: Balance) {
+            staking::staking::withdraw_unlocked(contract, staker, amount).unwrap();
+        }
+
+        /// Claims the rewards of the staker and returns them. The reserve is funded with them
+        /// beforehand, and the reputation is only checkpointed since minting it is a
+        /// cross-contract call.
+        fn get_reward(contract: &mut StakingContract, staker: AccountId) -> Balance {
+            contract.staking.reward_reserve += contract.earned(staker).unwrap();
+            let ids = contract.staking.positions_of(&staker);
+            let rewards = staking::staking::take_rewards(contract, &ids).unwrap();
+            contract.checkpoint_reputation(staker).unwrap();
+            rewards
+        }
+
+        /// Withdraws all the unlocked tokens of the staker and claims their rewards, like `exit`,
+        /// and returns the amount transferred.
+        fn exit(contract: &mut StakingContract, staker: AccountId) -> Balance {
+            let balance = staking::staking::unlocked_balance(contract, staker).unwrap();
+            if balance > 0 {
+                withdraw(contract, staker, balance);
+            }
+            balance + get_reward(contract, staker)
+        }
+
+        fn now() -> Timestamp {
+            ink::env::block_timestamp::<ink::env::DefaultEnvironment>()
+        }
+
+        #[ink::test]
+        fn topping_up_does_not_pay_retroactively() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+
+            stake(&mut contract, alice, 100);
+            set_time(10);
+            stake(&mut contract, alice, 900);
+            set_time(20);
+
+            // 10 ms at 50 per ms each, regardless of the balance added at t = 10.
+            assert_eq!(contract.earned(alice).unwrap(), 1_000);
+        }
+
+        #[ink::test]
+        fn staking_for_settles_and_tops_up_the_beneficiary() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+            let bob = AccountId::from([0x4; 32]);
+
+            stake(&mut contract, alice, 100);
+            let id = stake(&mut contract, bob, 100);
+            set_time(REPUTATION_PERIOD);
+            let accrued = contract.accrued_reputation(bob).unwrap();
+
+            stake_for(&mut contract, bob, 300);
+
+            // Bob's flexible position is topped up, with the rewards it earned so far settled.
+            assert_eq!(contract.staking.positions_of(&bob), vec![id]);
+            let position = contract.staking.positions.get(&id).unwrap();
+            assert_eq!(position.amount, 400);
+            assert_eq!(position.rewards, 25 * REPUTATION_PERIOD as Balance);
+            assert_eq!(
+                position.reward_per_token_paid,
+                contract.staking.reward_per_token_stored
+            );
+            assert_eq!(contract.balance_of(bob), 400);
+            assert_eq!(contract.staking.total_staked, 500);
+
+            // His reputation is checkpointed at the balance it accrued with.
+            assert_eq!(
+                contract.reputation.reputation_accrued.get(&bob),
+                Some(accrued)
+            );
+            assert_eq!(
+                contract.reputation.reputation_last_update.get(&bob),
+                Some(REPUTATION_PERIOD)
+            );
+
+            set_time(2 * REPUTATION_PERIOD);
+            assert_eq!(
+                contract.earned(bob).unwrap(),
+                (25 + 40) * REPUTATION_PERIOD as Balance
+            );
+            assert_eq!(
+                contract.earned(alice).unwrap(),
+                (25 + 10) * REPUTATION_PERIOD as Balance
+            );
+        }
+
+        #[ink::test]
+        fn withdrawing_keeps_accrued_rewards() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+
+            stake(&mut contract, alice, 100);
+            set_time(10);
+            withdraw(&mut contract, alice, 100);
+            set_time(20);
+
+            assert_eq!(contract.earned(alice).unwrap(), 500);
+        }
+
+        #[ink::test]
+        fn queries_report_rewards_at_the_current_block_without_storing_them() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+            let bob = AccountId::from([0x4; 32]);
+
+            stake(&mut contract, alice, 100);
+            stake(&mut contract, bob, 400);
+            set_time(10);
+
+            assert_eq!(contract.pending_reward(alice), Ok(100));
+            assert_eq!(contract.pending_reward(bob), Ok(400));
+            assert_eq!(Staking::reward_per_token(&contract), Ok(10u128.pow(18)));
+            assert_eq!(contract.current_reward_rate(), Ok(50));
+            assert_eq!(contract.last_update_time(), 0);
+            assert_eq!(contract.staking.reward_per_token_stored, 0);
+
+            set_time(emission::ERA_DURATION);
+            assert_eq!(contract.current_reward_rate(), Ok(25));
+        }
+
+        #[ink::test]
+        fn rewards_are_conserved_across_interleaved_calls() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+            let bob = AccountId::from([0x4; 32]);
+
+            stake(&mut contract, alice, 100);
+            set_time(10);
+            stake(&mut contract, bob, 400);
+            set_time(20);
+            withdraw(&mut contract, alice, 100);
+            set_time(30);
+            let alice_paid = get_reward(&mut contract, alice);
+            set_time(40);
+            withdraw(&mut contract, bob, 400);
+            let bob_paid = get_reward(&mut contract, bob);
+
+            assert_eq!(alice_paid, 500 + 100);
+            assert_eq!(bob_paid, 400 + 500 + 500);
+            assert_eq!(alice_paid + bob_paid, 50 * 40);
+            assert_eq!(contract.earned(alice).unwrap(), 0);
+            assert_eq!(contract.earned(bob).unwrap(), 0);
+        }
+
+        #[ink::test]
+        fn exiting_claims_the_rewards_of_the_locked_positions_they_keep_earning() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+
+            stake(&mut contract, alice, 100);
+            stake_locked(&mut contract, alice, 100, 12 * MONTH);
+            set_time(10);
+
+            assert_eq!(exit(&mut contract, alice), 100 + 50 * 10);
+            assert_eq!(contract.balance_of(alice), 100);
+            assert_eq!(contract.pending_reward(alice), Ok(0));
+
+            set_time(20);
+            assert_eq!(contract.pending_reward(alice), Ok(50 * 10));
+        }
+
+        #[ink::test]
+        fn exiting_leaves_at_most_rounding_dust() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+            let bob = AccountId::from([0x4; 32]);
+            let charlie = AccountId::from([0x5; 32]);
+
+            // Odd amounts and a 1.5x lock, so that no share divides exactly.
+            stake(&mut contract, alice, 3);
+            stake_locked(&mut contract, bob, 7, 6 * MONTH);
+            set_time(5);
+            stake(&mut contract, charlie, 11);
+
+            set_time(7);
+            let alice_paid = exit(&mut contract, alice);
+            set_time(13);
+            let charlie_paid = exit(&mut contract, charlie);
+            // Bob's position is still locked, so exiting only claims its rewards.
+            let bob_paid = exit(&mut contract, bob);
+            assert_eq!(contract.balance_of(bob), 7);
+            set_time(6 * MONTH);
+            let bob_paid = bob_paid + exit(&mut contract, bob);
+
+            let emitted = 50 * 6 * MONTH as Balance;
+            let paid = alice_paid + bob_paid + charlie_paid - (3 + 7 + 11);
+            assert!(paid <= emitted);
+            assert!(emitted - paid <= 3);
+
+            for staker in [alice, bob, charlie] {
+                assert_eq!(contract.positions_of(staker), Vec::new());
+                assert_eq!(contract.pending_reward(staker), Ok(0));
+            }
+            assert_eq!(contract.staking.total_staked, 0);
+            assert_eq!(contract.staking.total_weighted, 0);
+        }
+
+        const POOL: Balance = 700_000_000 * 10u128.pow(18);
+
+        const MONTH: Timestamp = 30 * 24 * 60 * 60 * 1000;
+
+        #[ink::test]
+        fn lock_ups_multiply_the_reward_weight() {
+            assert_eq!(lockup::multiplier(0), 10_000);
+            assert_eq!(lockup::multiplier(6 * MONTH - 1), 10_000);
+            assert_eq!(lockup::multiplier(6 * MONTH), 15_000);
+            assert_eq!(lockup::multiplier(12 * MONTH), 20_000);
+            assert_eq!(lockup::multiplier(48 * MONTH), 20_000);
+
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+            let bob = AccountId::from([0x4; 32]);
+            let charlie = AccountId::from([0x5; 32]);
+
+            stake(&mut contract, alice, 100);
+            stake_locked(&mut contract, bob, 100, 6 * MONTH);
+            stake(&mut contract, charlie, 100);
+            stake_locked(&mut contract, charlie, 100, 12 * MONTH);
+            set_time(60);
+
+            assert_eq!(contract.staking.total_staked, 400);
+            assert_eq!(contract.staking.total_weighted, 550);
+            assert_eq!(contract.earned(alice).unwrap(), 3_000 * 100 / 550);
+            assert_eq!(contract.earned(bob).unwrap(), 3_000 * 150 / 550);
+            // Each position rounds its own share down.
+            assert_eq!(
+                contract.earned(charlie).unwrap(),
+                3_000 * 100 / 550 + 3_000 * 200 / 550
+            );
+        }
+
+        #[ink::test]
+        fn locked_tokens_cannot_be_withdrawn_before_expiry() {
+            let mut contract = new_contract();
+            let bob = AccountId::from([0x4; 32]);
+
+            let flexible = stake(&mut contract, bob, 100);
+            let locked = stake_locked(&mut contract, bob, 100, 6 * MONTH);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(bob);
+
+            assert_eq!(contract.withdraw(101), Err(StakingError::StakeLocked));
+            assert_eq!(
+                contract.withdraw_position(locked, 1),
+                Err(StakingError::StakeLocked)
+            );
+            assert_eq!(
+                contract.release_lock(locked),
+                Err(StakingError::StakeLocked)
+            );
+
+            set_time(6 * MONTH);
+            withdraw(&mut contract, bob, 150);
+
+            // The newest position is emptied first, and kept until its rewards are claimed.
+            let positions = contract.positions_of(bob);
+            assert_eq!(contract.balance_of(bob), 50);
+            assert_eq!(positions[0].0, flexible);
+            assert_eq!(positions[0].1.amount, 50);
+            assert_eq!(positions[1].0, locked);
+            assert_eq!(positions[1].1.amount, 0);
+        }
+
+        #[ink::test]
+        fn released_locks_stop_boosting_rewards() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+            let bob = AccountId::from([0x4; 32]);
+
+            stake(&mut contract, alice, 100);
+            let locked = stake_locked(&mut contract, bob, 100, 12 * MONTH);
+            set_time(12 * MONTH);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(alice);
+            contract.release_lock(locked).unwrap();
+            let alice_earned = contract.earned(alice).unwrap();
+            let bob_earned = contract.earned(bob).unwrap();
+
+            assert_eq!(contract.positions_of(bob)[0].1.multiplier, 10_000);
+            assert_eq!(contract.staking.total_weighted, 200);
+
+            set_time(12 * MONTH + 10);
+            assert_eq!(contract.earned(alice).unwrap() - alice_earned, 250);
+            assert_eq!(contract.earned(bob).unwrap() - bob_earned, 250);
+        }
+
+        #[ink::test]
+        fn positions_accrue_rewards_independently() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+
+            let first = stake(&mut contract, alice, 100);
+            set_time(10);
+            let second = stake(&mut contract, alice, 300);
+            set_time(20);
+
+            contract.update_position_reward(first).unwrap();
+            contract.update_position_reward(second).unwrap();
+
+            let positions = contract.positions_of(alice);
+            assert_eq!(positions.len(), 2);
+            assert_eq!(
+                positions[0],
+                (first, contract.staking.positions.get(&first).unwrap())
+            );
+            assert_eq!(positions[0].1.start_time, 0);
+            assert_eq!(positions[0].1.rewards, 500 + 125);
+            assert_eq!(positions[1].1.start_time, 10);
+            assert_eq!(positions[1].1.rewards, 375);
+            assert_eq!(contract.balance_of(alice), 400);
+            assert_eq!(contract.earned(alice).unwrap(), 1_000);
+        }
+
+        #[ink::test]
+        fn positions_can_only_be_managed_by_their_owner() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+            let bob = AccountId::from([0x4; 32]);
+
+            let id = stake(&mut contract, alice, 100);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(bob);
+
+            assert_eq!(
+                contract.withdraw_position(id, 100),
+                Err(StakingError::NotPositionOwner)
+            );
+            assert_eq!(
+                contract.claim_position(id),
+                Err(StakingError::NotPositionOwner)
+            );
+            assert_eq!(
+                contract.claim_position(id + 1),
+                Err(StakingError::PositionNotFound)
+            );
+        }
+
+        #[ink::test]
+        fn accounts_hold_a_bounded_number_of_positions() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+
+            for _ in 0..MAX_POSITIONS {
+                stake(&mut contract, alice, 1);
+            }
+
+            let position = Position::new(alice, 1, 0, 0).unwrap();
+            assert_eq!(
+                contract.staking.open_position(position),
+                Err(StakingError::TooManyPositions)
+            );
+        }
+
+        #[ink::test]
+        fn compounding_tops_up_the_newest_flexible_position() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+
+            let flexible = stake(&mut contract, alice, 200);
+            let locked = stake_locked(&mut contract, alice, 100, 12 * MONTH);
+            set_time(10);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(alice);
+
+            assert_eq!(
+                contract.compound(),
+                Err(StakingError::InsufficientRewardReserve)
+            );
+            contract.staking.reward_reserve = 1_000;
+            contract.compound().unwrap();
+
+            let positions = contract.positions_of(alice);
+            assert_eq!(positions[0].0, flexible);
+            assert_eq!(positions[0].1.amount, 700);
+            assert_eq!(positions[0].1.start_time, 500 * 10 / 700);
+            assert_eq!(positions[1].0, locked);
+            assert_eq!(positions[1].1.amount, 100);
+            assert_eq!(contract.balance_of(alice), 800);
+            assert_eq!(contract.staking.total_staked, 800);
+            assert_eq!(contract.staking.total_weighted, 900);
+            assert_eq!(contract.staking.reward_reserve, 500);
+            assert_eq!(contract.pending_reward(alice), Ok(0));
+            assert_eq!(contract.compound(), Err(StakingError::NoStakingRewards));
+        }
+
+        #[ink::test]
+        fn rewards_in_another_token_cannot_be_compounded() {
+            set_time(0);
+            let mut contract = StakingContract::new(
+                AccountId::from([0x1; 32]),
+                AccountId::from([0x6; 32]),
+                AccountId::from([0x2; 32]),
+            );
+            let alice = AccountId::from([0x3; 32]);
+
+            contract.staking.emission_pool = 2 * 50 * emission::ERA_DURATION as Balance;
+            contract.staking.reward_reserve = 1_000;
+            stake(&mut contract, alice, 100);
+            set_time(10);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(alice);
+
+            assert_eq!(contract.compound(), Err(StakingError::RewardNotStakable));
+            assert_eq!(contract.pending_reward(alice), Ok(500));
+            assert_eq!(contract.compoundable_reward(alice), Ok(0));
+        }
+
+        #[ink::test]
+        fn compoundable_rewards_need_the_reserve_and_an_unpaused_contract() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+
+            stake(&mut contract, alice, 100);
+            contract.staking.reward_reserve = 499;
+            set_time(10);
+            assert_eq!(contract.compoundable_reward(alice), Ok(0));
+
+            contract.staking.reward_reserve = 500;
+            assert_eq!(contract.compoundable_reward(alice), Ok(500));
+
+            contract.pause().unwrap();
+            assert_eq!(contract.compoundable_reward(alice), Ok(0));
+        }
+
+        #[ink::test]
+        fn compounding_without_a_flexible_position_opens_one() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+
+            stake_locked(&mut contract, alice, 100, 12 * MONTH);
+            contract.staking.reward_reserve = 1_000;
+            set_time(10);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(alice);
+            contract.compound().unwrap();
+
+            let positions = contract.positions_of(alice);
+            assert_eq!(positions.len(), 2);
+            assert_eq!(positions[1].1.amount, 500);
+            assert_eq!(positions[1].1.start_time, 10);
+            assert_eq!(positions[1].1.multiplier, 10_000);
+        }
+
+        const UNBONDING_PERIOD: Timestamp = 7 * 24 * 60 * 60 * 1000;
+
+        #[ink::test]
+        fn unstake_requests_stop_accrual_until_cancelled() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+            let bob = AccountId::from([0x4; 32]);
+
+            contract.set_unbonding_period(UNBONDING_PERIOD).unwrap();
+            stake(&mut contract, alice, 100);
+            stake(&mut contract, bob, 100);
+            set_time(10);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(alice);
+            assert_eq!(contract.withdraw(100), Err(StakingError::UnbondingRequired));
+            contract.request_unstake(100).unwrap();
+
+            assert_eq!(
+                contract.pending_unlocks(alice),
+                vec![Unlock {
+                    amount: 100,
+                    release_time: 10 + UNBONDING_PERIOD,
+                }]
+            );
+            assert_eq!(contract.balance_of(alice), 0);
+            assert_eq!(contract.staking.total_unbonding, 100);
+
+            set_time(20);
+            assert_eq!(contract.earned(alice).unwrap(), 250);
+            assert_eq!(contract.earned(bob).unwrap(), 250 + 500);
+            assert_eq!(contract.pending_reputation(alice), Ok(0));
+
+            contract.cancel_unstake().unwrap();
+            set_time(30);
+
+            assert_eq!(contract.pending_unlocks(alice), Vec::new());
+            assert_eq!(contract.staking.total_unbonding, 0);
+            assert_eq!(contract.balance_of(alice), 100);
+            assert_eq!(contract.earned(alice).unwrap(), 250 + 250);
+        }
+
+        #[ink::test]
+        fn unstake_requests_complete_after_the_unbonding_period() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+
+            contract.set_unbonding_period(UNBONDING_PERIOD).unwrap();
+            stake(&mut contract, alice, 100);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(alice);
+            assert_eq!(
+                contract.complete_unstake(),
+                Err(StakingError::NothingToUnstake)
+            );
+            contract.request_unstake(40).unwrap();
+            set_time(UNBONDING_PERIOD - 1);
+            assert_eq!(
+                contract.complete_unstake(),
+                Err(StakingError::NothingToUnstake)
+            );
+            assert_eq!(
+                contract.emergency_withdraw(),
+                Err(StakingError::UnbondingRequired)
+            );
+            assert_eq!(contract.balance_of(alice), 60);
+        }
+
+        #[ink::test]
+        fn cumulative_emission_at_each_anniversary() {
+            let mut contract = new_contract_with_pool(POOL);
+            let alice = AccountId::from([0x3; 32]);
+
+            stake(&mut contract, alice, 1_000 * 10u128.pow(18));
+
+            for year in 1..=10 {
+                set_time(year * emission::ERA_DURATION);
+                contract.update_reward(alice).unwrap();
+
+                // 50% of the pool after the first year, 75% after the second one, and so on.
+                assert_eq!(contract.earned(alice).unwrap(), POOL - (POOL >> year));
+            }
+        }
+
+        #[ink::test]
+        fn emission_integrates_across_several_eras_in_one_update() {
+            let mut contract = new_contract_with_pool(POOL);
+            let alice = AccountId::from([0x3; 32]);
+
+            stake(&mut contract, alice, 1_000 * 10u128.pow(18));
+            set_time(3 * emission::ERA_DURATION + emission::ERA_DURATION / 2);
+
+            // Three full eras plus half of the fourth one, which releases 1/16th of the pool.
+            assert_eq!(
+                contract.earned(alice).unwrap(),
+                POOL - (POOL >> 3) + (POOL >> 4) / 2
+            );
+        }
+
+        #[ink::test]
+        fn emission_does_not_accrue_before_start() {
+            let mut contract = new_contract_with_pool(0);
+            let alice = AccountId::from([0x3; 32]);
+
+            stake(&mut contract, alice, 1_000);
+            set_time(emission::ERA_DURATION);
+
+            assert_eq!(contract.earned(alice).unwrap(), 0);
+        }
+
+        #[ink::test]
+        fn reputation_accrues_fractions_of_a_period() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+            let hour = REPUTATION_PERIOD / 24;
+
+            stake(&mut contract, alice, 10u128.pow(18));
+            contract.update_reputation(alice).unwrap();
+
+            // Hourly updates never complete a day, yet every hour is carried over.
+            for i in 1..=23 {
+                set_time(i * hour);
+                contract.update_reputation(alice).unwrap();
+            }
+
+            assert_eq!(
+                contract.reputation.reputation_accrued.get(&alice),
+                Some(23 * (10u128.pow(18) / 24))
+            );
+        }
+
+        fn reputation_points(contract: &StakingContract, staker: AccountId) -> u128 {
+            contract
+                .reputation
+                .reputation_accrued
+                .get(&staker)
+                .unwrap_or(0)
+                / 10u128.pow(18)
+        }
+
+        #[ink::test]
+        fn pending_reputation_counts_whole_points_without_checkpointing() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+
+            stake(&mut contract, alice, 3 * 10u128.pow(18));
+            set_time(REPUTATION_PERIOD / 2);
+
+            assert_eq!(contract.pending_reputation(alice), Ok(1));
+            assert_eq!(contract.reputation.reputation_accrued.get(&alice), Some(0));
+
+            set_time(REPUTATION_PERIOD);
+            assert_eq!(contract.pending_reputation(alice), Ok(3));
+        }
+
+        #[ink::test]
+        fn reputation_ignores_stake_added_just_before_a_claim() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+
+            stake(&mut contract, alice, 10u128.pow(18));
+            set_time(10 * REPUTATION_PERIOD);
+            stake(&mut contract, alice, 1_000 * 10u128.pow(18));
+            set_time(10 * REPUTATION_PERIOD + 1);
+            get_reward(&mut contract, alice);
+
+            // One token for ten days, the large stake only counts for a millisecond.
+            assert_eq!(reputation_points(&contract, alice), 10);
+        }
+
+        #[ink::test]
+        fn reputation_follows_the_balance_history() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+
+            stake(&mut contract, alice, 1_000 * 10u128.pow(18));
+            set_time(REPUTATION_PERIOD);
+            withdraw(&mut contract, alice, 999 * 10u128.pow(18));
+            set_time(2 * REPUTATION_PERIOD);
+            stake(&mut contract, alice, 9 * 10u128.pow(18));
+            set_time(4 * REPUTATION_PERIOD);
+            get_reward(&mut contract, alice);
+
+            assert_eq!(reputation_points(&contract, alice), 1_000 + 1 + 2 * 10);
+        }
+
+        #[ink::test]
+        fn funding_restarts_the_schedule_with_the_remaining_pool() {
+            let mut contract = new_contract_with_pool(0);
+            let alice = AccountId::from([0x3; 32]);
+
+            stake(&mut contract, alice, 1_000 * 10u128.pow(18));
+            contract.schedule_rewards(POOL).unwrap();
+            set_time(emission::ERA_DURATION);
+            contract.schedule_rewards(POOL).unwrap();
+            set_time(2 * emission::ERA_DURATION);
+
+            // Half of the first funding, then half of what was left plus the second funding.
+            assert_eq!(
+                contract.earned(alice).unwrap(),
+                POOL / 2 + (POOL / 2 + POOL) / 2
+            );
+        }
+
+        #[ink::test]
+        fn rewards_emitted_before_anyone_stakes_go_to_the_first_stakers() {
+            let mut contract = new_contract_with_pool(0);
+            let alice = AccountId::from([0x3; 32]);
+
+            contract.schedule_rewards(POOL).unwrap();
+            set_time(emission::ERA_DURATION / 2);
+            contract.schedule_rewards(0).unwrap();
+            set_time(emission::ERA_DURATION);
+            stake(&mut contract, alice, 1_000 * 10u128.pow(18));
+
+            // Restarting the schedule keeps the pool whole, and half a year of it was emitted.
+            assert_eq!(contract.last_update_time(), emission::ERA_DURATION / 2);
+            assert_eq!(contract.earned(alice).unwrap(), POOL / 4);
+
+            set_time(128 * emission::ERA_DURATION);
+            assert_eq!(contract.earned(alice).unwrap(), POOL);
+        }
+
+        #[ink::test]
+        fn get_reward_does_not_dip_into_staked_tokens() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+
+            stake(&mut contract, alice, 100);
+            set_time(10);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(alice);
+
+            assert_eq!(
+                contract.get_reward(),
+                Err(StakingError::InsufficientRewardReserve)
+            );
+            assert_eq!(contract.earned(alice).unwrap(), 500);
+        }
+
+        #[ink::test]
+        fn halving_period_change_reschedules_the_remaining_pool() {
+            let mut contract = new_contract_with_pool(POOL);
+            let alice = AccountId::from([0x3; 32]);
+
+            stake(&mut contract, alice, 1_000 * 10u128.pow(18));
+            set_time(emission::ERA_DURATION);
+            contract
+                .set_halving_period(emission::ERA_DURATION / 2)
+                .unwrap();
+            set_time(emission::ERA_DURATION + emission::ERA_DURATION / 2);
+
+            assert_eq!(contract.staking.halving_period, emission::ERA_DURATION / 2);
+            assert_eq!(contract.earned(alice).unwrap(), POOL / 2 + POOL / 4);
+        }
+
+        type Event = <StakingContract as ink::reflect::ContractEventBase>::Type;
+
+        /// Returns the events recorded so far, decoded, along with their number of topics.
+        fn recorded_events() -> Vec<(Event, usize)> {
+            ink::env::test::recorded_events()
+                .map(|event| {
+                    let decoded = <Event as scale::Decode>::decode(&mut &event.data[..])
+                        .expect("encountered invalid contract event data buffer");
+                    (decoded, event.topics.len())
+                })
+                .collect()
+        }
+
+        #[ink::test]
+        fn staking_events_have_the_staker_as_topic() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+
+            contract.set_unbonding_period(UNBONDING_PERIOD).unwrap();
+            let id = stake(&mut contract, alice, 100);
+            contract.staking.reward_reserve = 1_000;
+            set_time(10);
+
+            // `stake`, `withdraw` and `get_reward` transfer tokens, which the off-chain
+            // environment cannot do, so the messages below emit the same events without moving
+            // them. The e2e tests cover the transferring messages.
+            let recorded = recorded_events().len();
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(alice);
+            contract.compound().unwrap();
+            contract.request_unstake(600).unwrap();
+            contract.cancel_unstake().unwrap();
+
+            let events = recorded_events().split_off(recorded);
+            assert_eq!(events.len(), 5);
+            match &events[0] {
+                (Event::RewardPaid(event), 2) => {
+                    assert_eq!((event.staker, event.reward), (alice, 500))
+                }
+                _ => panic!("expected a RewardPaid event"),
+            }
+            match &events[1] {
+                (Event::Staked(event), 2) => {
+                    assert_eq!(
+                        (event.staker, event.position, event.amount),
+                        (alice, id, 500)
+                    )
+                }
+                _ => panic!("expected a Staked event"),
+            }
+            match &events[2] {
+                (Event::Withdrawn(event), 2) => {
+                    assert_eq!(
+                        (event.staker, event.position, event.amount),
+                        (alice, id, 600)
+                    )
+                }
+                _ => panic!("expected a Withdrawn event"),
+            }
+            match &events[3] {
+                (Event::UnstakeRequested(event), 2) => {
+                    assert_eq!((event.staker, event.amount), (alice, 600))
+                }
+                _ => panic!("expected an UnstakeRequested event"),
+            }
+            match &events[4] {
+                (Event::Staked(event), 2) => {
+                    assert_eq!((event.staker, event.amount), (alice, 600))
+                }
+                _ => panic!("expected a Staked event"),
+            }
+        }
+
+        #[ink::test]
+        fn halving_period_change_emits_the_new_reward_rate() {
+            let mut contract = new_contract_with_pool(POOL);
+
+            contract
+                .set_halving_period(emission::ERA_DURATION / 2)
+                .unwrap();
+
+            let events = recorded_events();
+            assert_eq!(events.len(), 2);
+            match &events[0] {
+                (Event::HalvingPeriodChanged(event), 1) => {
+                    assert_eq!(event.halving_period, emission::ERA_DURATION / 2)
+                }
+                _ => panic!("expected a HalvingPeriodChanged event"),
+            }
+            match &events[1] {
+                (Event::RewardRateChanged(event), 1) => {
+                    assert_eq!(event.era_emission, POOL / 2);
+                    assert_eq!(event.halving_period, emission::ERA_DURATION / 2);
+                }
+                _ => panic!("expected a RewardRateChanged event"),
+            }
+        }
+
+        #[ink::test]
+        fn only_admins_and_managers_can_change_the_configuration() {
+            let mut contract = new_contract();
+            let bob = AccountId::from([0x4; 32]);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(bob);
+
+            assert_eq!(
+                contract.set_halving_period(1),
+                Err(StakingError::AccessControlError(
+                    AccessControlError::MissingRole
+                ))
+            );
+            assert_eq!(
+                contract.set_reputation_token(bob),
+                Err(StakingError::AccessControlError(
+                    AccessControlError::MissingRole
+                ))
+            );
+            assert_eq!(
+                contract.set_unbonding_period(1),
+                Err(StakingError::AccessControlError(
+                    AccessControlError::MissingRole
+                ))
+            );
+            assert_eq!(contract.staking.halving_period, emission::ERA_DURATION);
+            assert_eq!(contract.staking.unbonding_period, 0);
+        }
+
+        #[ink::test]
+        fn pausing_halts_staking_withdrawals_and_claims() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+
+            contract.pause().unwrap();
+            assert!(contract.paused());
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(alice);
+            assert_eq!(
+                contract.stake(100),
+                Err(StakingError::PausableError(PausableError::Paused))
+            );
+            assert_eq!(
+                contract.withdraw(100),
+                Err(StakingError::PausableError(PausableError::Paused))
+            );
+            assert_eq!(
+                contract.get_reward(),
+                Err(StakingError::PausableError(PausableError::Paused))
+            );
+            assert_eq!(
+                contract.pause(),
+                Err(StakingError::AccessControlError(
+                    AccessControlError::MissingRole
+                ))
+            );
+        }
+
+        #[ink::test]
+        fn emergency_withdrawals_drop_pending_rewards_and_reputation() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+            let bob = AccountId::from([0x4; 32]);
+
+            stake(&mut contract, alice, 100);
+            stake(&mut contract, bob, 100);
+            set_time(10);
+
+            // What `emergency_withdraw` does before the PSP22 transfer.
+            assert_eq!(
+                staking::staking::abandon_positions(&mut contract, alice, true),
+                Ok(100)
+            );
+
+            assert_eq!(contract.positions_of(alice), Vec::new());
+            assert_eq!(contract.balance_of(alice), 0);
+            assert_eq!(contract.earned(alice).unwrap(), 0);
+            assert_eq!(contract.accrued_reputation(alice), Ok(0));
+            // Nothing was settled, so Bob takes the share of Alice since the last update.
+            assert_eq!(contract.earned(bob).unwrap(), 500);
+            assert_eq!(contract.staking.total_staked, 100);
+            assert_eq!(contract.staking.total_weighted, 100);
+            assert_eq!(
+                contract.staking.emission_pool,
+                2 * 50 * emission::ERA_DURATION as Balance
+            );
+        }
+
+        #[ink::test]
+        fn emergency_withdrawals_keep_the_locked_positions_unless_paused() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+
+            stake(&mut contract, alice, 100);
+            let locked = stake_locked(&mut contract, alice, 100, 12 * MONTH);
+            set_time(30);
+
+            assert_eq!(
+                staking::staking::abandon_positions(&mut contract, alice, false),
+                Ok(100)
+            );
+
+            assert_eq!(contract.staking.positions_of(&alice), vec![locked]);
+            assert_eq!(contract.balance_of(alice), 100);
+            assert_eq!(contract.earned(alice).unwrap(), 50 * 30);
+
+            assert_eq!(
+                staking::staking::abandon_positions(&mut contract, alice, true),
+                Ok(100)
+            );
+            assert_eq!(contract.positions_of(alice), Vec::new());
+        }
+
+        #[ink::test]
+        fn only_reward_distributors_can_fund_rewards() {
+            let mut contract = new_contract();
+            let bob = AccountId::from([0x4; 32]);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(bob);
+
+            assert_eq!(
+                contract.fund_rewards(1_000),
+                Err(StakingError::AccessControlError(
+                    AccessControlError::MissingRole
+                ))
+            );
+        }
+
+        #[ink::test]
+        fn only_the_staker_and_their_operator_can_claim_for_them() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+            let bob = AccountId::from([0x4; 32]);
+            let charlie = AccountId::from([0x5; 32]);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(alice);
+            assert_eq!(contract.operator_of(alice), None);
+            assert_eq!(contract.set_operator(Some(bob)), Ok(()));
+            assert_eq!(contract.operator_of(alice), Some(bob));
+
+            // Alice has no rewards yet, so the claims transfer nothing.
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(charlie);
+            assert_eq!(contract.claim_for(alice), Err(StakingError::NotOperator));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(bob);
+            assert_eq!(contract.claim_for(alice), Ok(()));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(alice);
+            assert_eq!(contract.set_operator(None), Ok(()));
+            assert_eq!(contract.claim_for(alice), Ok(()));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(bob);
+            assert_eq!(contract.claim_for(alice), Err(StakingError::NotOperator));
+        }
+
+        #[ink::test]
+        fn rewards_can_be_redirected_to_another_recipient() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+            let vault = AccountId::from([0x6; 32]);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(alice);
+            assert_eq!(contract.reward_recipient(alice), alice);
+
+            assert_eq!(contract.set_reward_recipient(Some(vault)), Ok(()));
+            assert_eq!(contract.reward_recipient(alice), vault);
+
+            assert_eq!(contract.set_reward_recipient(None), Ok(()));
+            assert_eq!(contract.reward_recipient(alice), alice);
+
+            // Naming the staker themselves resets the recipient as well.
+            assert_eq!(contract.set_reward_recipient(Some(vault)), Ok(()));
+            assert_eq!(contract.set_reward_recipient(Some(alice)), Ok(()));
+            assert_eq!(contract.reward_recipient(alice), alice);
+
+            let events = recorded_events();
+            assert_eq!(events.len(), 4);
+            match &events[0] {
+                (Event::RewardRecipientChanged(event), 2) => {
+                    assert_eq!((event.staker, event.recipient), (alice, Some(vault)))
+                }
+                _ => panic!("expected a RewardRecipientChanged event"),
+            }
+            match &events[1] {
+                (Event::RewardRecipientChanged(event), 2) => {
+                    assert_eq!((event.staker, event.recipient), (alice, None))
+                }
+                _ => panic!("expected a RewardRecipientChanged event"),
+            }
+            match &events[3] {
+                (Event::RewardRecipientChanged(event), 2) => {
+                    assert_eq!((event.staker, event.recipient), (alice, None))
+                }
+                _ => panic!("expected a RewardRecipientChanged event"),
+            }
+        }
+
+        #[ink::test]
+        fn receipt_token_can_only_change_without_stake() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+            let receipt_token = AccountId::from([0x7; 32]);
+
+            assert_eq!(contract.set_receipt_token(Some(receipt_token)), Ok(()));
+            assert_eq!(contract.staking.receipt_token, Some(receipt_token));
+
+            stake(&mut contract, alice, 100);
+            assert_eq!(
+                contract.set_receipt_token(None),
+                Err(StakingError::StakeOutstanding)
+            );
+        }
+
+        #[ink::test]
+        fn share_transfers_move_the_stake_behind_them() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+            let bob = AccountId::from([0x4; 32]);
+            let receipt_token = AccountId::from([0x7; 32]);
+
+            contract.set_receipt_token(Some(receipt_token)).unwrap();
+            stake(&mut contract, alice, 100);
+            stake_locked(&mut contract, alice, 100, 6 * MONTH);
+            set_time(10);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(alice);
+            assert_eq!(
+                contract.transfer_stake(alice, bob, 40),
+                Err(StakingError::NotReceiptToken)
+            );
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(receipt_token);
+            assert_eq!(
+                contract.transfer_stake(alice, bob, 101),
+                Err(StakingError::StakeLocked)
+            );
+            assert_eq!(contract.transfer_stake(alice, bob, 40), Ok(()));
+
+            assert_eq!(contract.balance_of(alice), 160);
+            assert_eq!(contract.balance_of(bob), 40);
+            // The rewards earned before the transfer stay with alice.
+            assert_eq!(contract.earned(alice).unwrap(), 500);
+            assert_eq!(contract.earned(bob).unwrap(), 0);
+
+            set_time(20);
+            assert_eq!(contract.earned(bob).unwrap(), 500 * 40 / 250);
+        }
+
+        #[ink::test]
+        fn vesting_rewards_release_linearly() {
+            let mut vesting = Vesting::default();
+            vesting.add(1_000, 0, 100).unwrap();
+
+            assert_eq!(vesting.vested_at(0), Ok(0));
+            assert_eq!(vesting.vested_at(25), Ok(250));
+            assert_eq!(vesting.vested_at(100), Ok(1_000));
+            assert_eq!(vesting.vested_at(200), Ok(1_000));
+
+            assert_eq!(vesting.release(25), Ok(250));
+            assert_eq!(vesting.vested_at(25), Ok(0));
+            // What is left keeps releasing at the same pace.
+            assert_eq!(vesting.vested_at(50), Ok(250));
+            assert_eq!(vesting.release(100), Ok(750));
+            assert_eq!(vesting.total(), Ok(0));
+        }
+
+        #[ink::test]
+        fn rewards_claimed_while_vesting_merge_into_one_schedule() {
+            let mut vesting = Vesting::default();
+            vesting.add(1_000, 0, 100).unwrap();
+            vesting.add(500, 50, 100).unwrap();
+
+            // The 500 vested so far stay releasable, the rest restarts with the new rewards.
+            assert_eq!(vesting.vested_at(50), Ok(500));
+            assert_eq!(
+                (vesting.vesting, vesting.start, vesting.end),
+                (1_000, 50, 150)
+            );
+            assert_eq!(vesting.vested_at(100), Ok(500 + 500));
+            assert_eq!(vesting.vested_at(150), Ok(1_500));
+        }
+
+        #[ink::test]
+        fn vesting_is_configured_by_reward_managers() {
+            let mut contract = new_contract();
+            let bob = AccountId::from([0x4; 32]);
+
+            assert_eq!(
+                contract.set_vesting(30 * 24 * 60 * 60 * 1000, 10_001),
+                Err(StakingError::BasisPointsOutOfRange)
+            );
+            assert_eq!(
+                contract.set_vesting(30 * 24 * 60 * 60 * 1000, 5_000),
+                Ok(())
+            );
+            assert_eq!(contract.staking.early_exit_penalty, 5_000);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(bob);
+            assert_eq!(
+                contract.set_vesting(0, 0),
+                Err(StakingError::AccessControlError(
+                    AccessControlError::MissingRole
+                ))
+            );
+            assert_eq!(contract.release_vested(), Err(StakingError::NothingVested));
+            assert_eq!(
+                contract.release_vested_early(),
+                Err(StakingError::NothingVested)
+            );
+            assert_eq!(contract.vested_amount(bob), Ok(0));
+        }
+
+        #[ink::test]
+        fn forfeited_rewards_go_back_to_the_emission_pool_without_a_treasury() {
+            let mut contract = new_contract_with_pool(0);
+            let bob = AccountId::from([0x4; 32]);
+
+            contract.set_vesting(100, 10_000).unwrap();
+            // What claiming 1_000 rewards at genesis leaves in the ledger.
+            let mut vesting = Vesting::default();
+            vesting.add(1_000, 0, 100).unwrap();
+            contract.staking.vestings.insert(&bob, &vesting);
+            contract.staking.total_vesting = 1_000;
+
+            // Nothing has vested yet, so the full penalty leaves nothing to transfer.
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(bob);
+            assert_eq!(contract.release_vested_early(), Ok(()));
+
+            assert_eq!(contract.vesting_of(bob), None);
+            assert_eq!(contract.staking.total_vesting, 0);
+            assert_eq!(contract.staking.reward_reserve, 1_000);
+            // The schedule is left alone until the next funding.
+            assert_eq!(contract.staking.emission_pool, 0);
+            assert_eq!(contract.staking.emission_start, 0);
+            assert_eq!(contract.staking.undistributed_rewards, 1_000);
+
+            set_time(100);
+            contract.schedule_rewards(0).unwrap();
+            assert_eq!(contract.staking.emission_pool, 1_000);
+            assert_eq!(contract.staking.undistributed_rewards, 0);
+        }
+
+        #[ink::test]
+        fn vesting_ledgers_beyond_the_total_are_rejected() {
+            let mut contract = new_contract_with_pool(0);
+            let bob = AccountId::from([0x4; 32]);
+
+            contract.set_vesting(100, 10_000).unwrap();
+            let mut vesting = Vesting::default();
+            vesting.add(1_000, 0, 100).unwrap();
+            contract.staking.vestings.insert(&bob, &vesting);
+            contract.staking.total_vesting = 999;
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(bob);
+            assert_eq!(
+                contract.release_vested_early(),
+                Err(StakingError::OverflowError)
+            );
+            // The failed call is not reverted off-chain, so the ledger is put back.
+            contract.staking.vestings.insert(&bob, &vesting);
+            set_time(100);
+            assert_eq!(contract.release_vested(), Err(StakingError::OverflowError));
+        }
+
+        #[ink::test]
+        fn vesting_rewards_cannot_be_compounded() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+
+            contract.set_vesting(100, 0).unwrap();
+            contract.staking.reward_reserve = 1_000;
+            stake(&mut contract, alice, 100);
+            set_time(10);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(alice);
+
+            assert_eq!(contract.compound(), Err(StakingError::RewardsVesting));
+            assert_eq!(contract.pending_reward(alice), Ok(500));
+            assert_eq!(contract.compoundable_reward(alice), Ok(0));
+        }
+
+        const DAY: Timestamp = 24 * 60 * 60 * 1000;
+
+        #[ink::test]
+        fn withdrawal_fees_stop_once_deposits_are_old_enough() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+
+            contract.set_withdrawal_fee(200, 7 * DAY).unwrap();
+            let position = Position::new(alice, 1_000, now(), 0).unwrap();
+
+            let mut young = position;
+            assert_eq!(contract.staking.withdrawal_fee(&mut young, 300, 0), Ok(6));
+            assert_eq!(young.fee_liable, 700);
+            let mut young = position;
+            assert_eq!(
+                contract
+                    .staking
+                    .withdrawal_fee(&mut young, 1_000, 7 * DAY - 1),
+                Ok(20)
+            );
+            assert_eq!(young.fee_liable, 0);
+            let mut old = position;
+            assert_eq!(
+                contract.staking.withdrawal_fee(&mut old, 1_000, 7 * DAY),
+                Ok(0)
+            );
+
+            contract.set_withdrawal_fee(0, 7 * DAY).unwrap();
+            let mut young = position;
+            assert_eq!(contract.staking.withdrawal_fee(&mut young, 1_000, 0), Ok(0));
+        }
+
+        #[ink::test]
+        fn topping_up_restarts_the_fee_period_of_the_tokens_still_charged() {
+            let alice = AccountId::from([0x3; 32]);
+
+            let mut position = Position::new(alice, 1_000, 0, 0).unwrap();
+            position.add_fee_liable(100, 3 * DAY, 7 * DAY).unwrap();
+            assert_eq!(position.fee_liable_at(8 * DAY, 7 * DAY), 1_100);
+            assert_eq!(position.fee_liable_at(10 * DAY, 7 * DAY), 0);
+
+            // Tokens whose period is over are no longer charged.
+            let mut position = Position::new(alice, 1_000, 0, 0).unwrap();
+            position.add_fee_liable(100, 7 * DAY, 7 * DAY).unwrap();
+            assert_eq!(position.fee_liable_at(7 * DAY, 7 * DAY), 100);
+        }
+
+        #[ink::test]
+        fn fresh_tokens_pay_the_fee_in_an_aged_position() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+
+            contract.set_withdrawal_fee(200, 7 * DAY).unwrap();
+            let id = stake(&mut contract, alice, 1_000);
+            set_time(70 * DAY);
+            stake_for(&mut contract, alice, 100);
+
+            // The position looks old on average, but the tokens just added are charged first.
+            let position = contract.staking.positions.get(&id).unwrap();
+            assert_eq!((position.amount, position.fee_liable), (1_100, 100));
+            assert_eq!(
+                staking::staking::withdraw_unlocked(&mut contract, alice, 100),
+                Ok(2)
+            );
+            assert_eq!(
+                staking::staking::withdraw_unlocked(&mut contract, alice, 1_000),
+                Ok(0)
+            );
+        }
+
+        #[ink::test]
+        fn compounded_rewards_are_not_charged_a_withdrawal_fee() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+
+            contract.set_withdrawal_fee(200, 7 * DAY).unwrap();
+            let id = stake(&mut contract, alice, 1_000);
+            set_time(7 * DAY);
+            staking::staking::credit_stake(&mut contract, alice, 100, false).unwrap();
+
+            let position = contract.staking.positions.get(&id).unwrap();
+            assert_eq!((position.amount, position.fee_liable), (1_100, 0));
+        }
+
+        #[ink::test]
+        fn received_stake_is_charged_like_a_deposit() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+            let bob = AccountId::from([0x4; 32]);
+            let receipt_token = AccountId::from([0x7; 32]);
+
+            contract.set_receipt_token(Some(receipt_token)).unwrap();
+            contract.set_withdrawal_fee(200, 7 * DAY).unwrap();
+            let id = stake(&mut contract, bob, 1_000);
+            set_time(70 * DAY);
+            stake(&mut contract, alice, 100);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(receipt_token);
+            contract.transfer_stake(alice, bob, 100).unwrap();
+
+            let position = contract.staking.positions.get(&id).unwrap();
+            assert_eq!((position.amount, position.fee_liable), (1_100, 100));
+        }
+
+        #[ink::test]
+        fn canceled_unstake_requests_are_not_charged_twice() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+
+            contract.set_unbonding_period(UNBONDING_PERIOD).unwrap();
+            contract.set_withdrawal_fee(200, 7 * DAY).unwrap();
+            stake(&mut contract, alice, 1_000);
+            let pool = contract.staking.emission_pool;
+
+            // Without a treasury, the fee is kept for the emission pool.
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(alice);
+            contract.request_unstake(1_000).unwrap();
+            assert_eq!(contract.pending_unlocks(alice)[0].amount, 980);
+            assert_eq!(contract.staking.reward_reserve, 20);
+            assert_eq!(contract.staking.undistributed_rewards, 20);
+            assert_eq!(contract.staking.emission_pool, pool);
+
+            contract.cancel_unstake().unwrap();
+            contract.request_unstake(980).unwrap();
+            assert_eq!(contract.pending_unlocks(alice)[0].amount, 980);
+            assert_eq!(contract.staking.reward_reserve, 20);
+        }
+
+        #[ink::test]
+        fn withdrawal_fees_need_a_treasury_when_rewards_are_another_token() {
+            set_time(0);
+            let mut contract = StakingContract::new(
+                AccountId::from([0x1; 32]),
+                AccountId::from([0x6; 32]),
+                AccountId::from([0x2; 32]),
+            );
+            let alice = AccountId::from([0x3; 32]);
+            let treasury = AccountId::from([0x8; 32]);
+
+            contract.set_withdrawal_fee(200, 7 * DAY).unwrap();
+            let mut position = Position::new(alice, 1_000, 0, 0).unwrap();
+            assert_eq!(
+                contract.staking.withdrawal_fee(&mut position, 1_000, 0),
+                Ok(0)
+            );
+
+            contract.set_treasury(Some(treasury)).unwrap();
+            let mut position = Position::new(alice, 1_000, 0, 0).unwrap();
+            assert_eq!(
+                contract.staking.withdrawal_fee(&mut position, 1_000, 0),
+                Ok(20)
+            );
+        }
+
+        #[ink::test]
+        fn withdrawal_fee_is_set_by_admins() {
+            let mut contract = new_contract();
+            let bob = AccountId::from([0x4; 32]);
+
+            assert_eq!(
+                contract.set_withdrawal_fee(10_001, 7 * DAY),
+                Err(StakingError::BasisPointsOutOfRange)
+            );
+            assert_eq!(contract.set_withdrawal_fee(200, 7 * DAY), Ok(()));
+            assert_eq!(contract.staking.withdrawal_fee, 200);
+            assert_eq!(contract.staking.withdrawal_fee_period, 7 * DAY);
+
+            match recorded_events().last() {
+                Some((Event::WithdrawalFeeChanged(event), 1)) => {
+                    assert_eq!(event.withdrawal_fee, 200);
+                    assert_eq!(event.withdrawal_fee_period, 7 * DAY);
+                }
+                _ => panic!("expected a WithdrawalFeeChanged event"),
+            }
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(bob);
+            assert_eq!(
+                contract.set_withdrawal_fee(0, 0),
+                Err(StakingError::AccessControlError(
+                    AccessControlError::MissingRole
+                ))
+            );
+        }
+
+        #[ink::test]
+        fn treasury_is_set_by_admins() {
+            let mut contract = new_contract();
+            let bob = AccountId::from([0x4; 32]);
+            let treasury = AccountId::from([0x8; 32]);
+
+            assert_eq!(contract.set_treasury(Some(treasury)), Ok(()));
+            assert_eq!(contract.staking.treasury, Some(treasury));
+
+            match recorded_events().last() {
+                Some((Event::TreasuryChanged(event), 1)) => {
+                    assert_eq!(event.treasury, Some(treasury));
+                }
+                _ => panic!("expected a TreasuryChanged event"),
+            }
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(bob);
+            assert_eq!(
+                contract.set_treasury(None),
+                Err(StakingError::AccessControlError(
+                    AccessControlError::MissingRole
+                ))
+            );
+        }
+
+        #[ink::test]
+        fn emergency_withdrawals_are_not_charged_a_withdrawal_fee() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+
+            contract.set_withdrawal_fee(200, 7 * DAY).unwrap();
+            stake(&mut contract, alice, 1_000);
+
+            assert_eq!(
+                staking::staking::abandon_positions(&mut contract, alice, true),
+                Ok(1_000)
+            );
+            assert_eq!(contract.staking.reward_reserve, 0);
+            assert_eq!(contract.staking.undistributed_rewards, 0);
+        }
+
+        #[ink::test]
+        fn stakes_must_stay_within_the_limits() {
+            let mut contract = new_contract();
+            let alice = AccountId::from([0x3; 32]);
+            let bob = AccountId::from([0x4; 32]);
+
+            contract
+                .set_stake_limits(100, Some(1_000), Some(1_500))
+                .unwrap();
+            stake(&mut contract, alice, 900);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(alice);
+            assert_eq!(contract.stake(99), Err(StakingError::BelowMinimum));
+            assert_eq!(contract.stake(101), Err(StakingError::CapExceeded));
+            assert_eq!(
+                contract.stake_locked(101, 12 * MONTH),
+                Err(StakingError::CapExceeded)
+            );
+            assert_eq!(contract.stake_for(bob, 50), Err(StakingError::BelowMinimum));
+            assert_eq!(contract.staking.ensure_stake_limits(&alice, 100), Ok(()));
+
+            stake(&mut contract, bob, 500);
+            assert_eq!(
+                contract.staking.ensure_stake_limits(&bob, 101),
+                Err(StakingError::CapExceeded)
+            );
+            assert_eq!(contract.staking.ensure_stake_limits(&bob, 100), Ok(()));
+
+            contract.set_stake_limits(0, None, None).unwrap();
+            assert_eq!(contract.staking.ensure_stake_limits(&alice, 10_000), Ok(()));
+        }
+
+        #[ink::test]
+        fn stake_limits_are_set_by_admins() {
+            let mut contract = new_contract();
+            let bob = AccountId::from([0x4; 32]);
+
+            assert_eq!(contract.set_stake_limits(100, Some(1_000), None), Ok(()));
+            assert_eq!(contract.staking.min_stake, 100);
+            assert_eq!(contract.staking.max_stake_per_account, Some(1_000));
+            assert_eq!(contract.staking.max_total_staked, None);
+
+            match recorded_events().last() {
+                Some((Event::StakeLimitsChanged(event), 1)) => {
+                    assert_eq!(event.min_stake, 100);
+                    assert_eq!(event.max_stake_per_account, Some(1_000));
+                }
+                _ => panic!("expected a StakeLimitsChanged event"),
+            }
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(bob);
+            assert_eq!(
+                contract.set_stake_limits(0, None, None),
+                Err(StakingError::AccessControlError(
+                    AccessControlError::MissingRole
+                ))
+            );
+        }
+
+        #[ink::test]
+        fn early_releases_are_disabled_without_a_penalty() {
+            let mut contract = new_contract();
 
-            let rate = time_elapsed
-                .checked_div(REPUTATION_PERIOD)
-                .ok_or(StakingError::DivideByZero)?;
+            assert_eq!(
+                contract.release_vested_early(),
+                Err(StakingError::EarlyExitDisabled)
+            );
+        }
 
-            let balance = self.staking.balances.get(&staker).unwrap_or(0);
+        const EXTRA_TOKEN: [u8; 32] = [0x9; 32];
+
+        /// Returns a contract distributing `EXTRA_TOKEN` as an extra reward token, funded with
+        /// `amount` tokens over `duration` like `notify_reward_amount` does after its transfer.
+        fn new_contract_with_extra_rewards(
+            amount: Balance,
+            duration: Timestamp,
+        ) -> StakingContract {
+            let mut contract = new_contract();
+            let token = AccountId::from(EXTRA_TOKEN);
+            contract.add_reward_token(token).unwrap();
+            contract.add_extra_rewards(token, amount, duration).unwrap();
+            contract
+        }
 
-            let new_reputation = balance
-                .checked_mul(rate as u128)
-                .ok_or(StakingError::OverflowError)?
-                .checked_div(10u128.pow(18))
-                .ok_or(StakingError::DivideByZero)?;
+        #[ink::test]
+        fn extra_rewards_are_shared_by_weight_until_the_period_finishes() {
+            let mut contract = new_contract_with_extra_rewards(3_000, 100);
+            let token = AccountId::from(EXTRA_TOKEN);
+            let alice = AccountId::from([0x3; 32]);
+            let bob = AccountId::from([0x4; 32]);
+
+            stake(&mut contract, alice, 100);
+            stake_locked(&mut contract, bob, 100, 12 * MONTH);
+            set_time(50);
+
+            assert_eq!(contract.extra_earned(token, alice), Ok(500));
+            assert_eq!(contract.extra_earned(token, bob), Ok(1_000));
+            assert_eq!(
+                contract.pending_rewards(alice),
+                Ok(vec![
+                    (
+                        contract.staking.reward_token,
+                        contract.earned(alice).unwrap()
+                    ),
+                    (token, 500)
+                ])
+            );
 
-            self.reputation.reputation_last_update.insert(&staker, &now);
+            set_time(200);
+            assert_eq!(contract.extra_earned(token, alice), Ok(1_000));
+            assert_eq!(contract.extra_earned(token, bob), Ok(2_000));
+        }
 
-            ReputationRef::update_reputation(
-                &self.reputation.reputation_token,
-                staker,
-                new_reputation,
-            )?;
-            Ok(())
+        #[ink::test]
+        fn extra_rewards_outlive_the_withdrawn_positions() {
+            let mut contract = new_contract_with_extra_rewards(1_000, 100);
+            let token = AccountId::from(EXTRA_TOKEN);
+            let alice = AccountId::from([0x3; 32]);
+            let bob = AccountId::from([0x4; 32]);
+
+            stake(&mut contract, alice, 100);
+            stake(&mut contract, bob, 100);
+            set_time(20);
+            withdraw(&mut contract, alice, 100);
+            set_time(60);
+
+            // 20 ms shared by two stakers, then 40 ms for bob alone.
+            assert_eq!(contract.extra_earned(token, alice), Ok(100));
+            assert_eq!(contract.extra_earned(token, bob), Ok(100 + 400));
         }
-    }
 
-    impl Staking for StakingContract {}
+        #[ink::test]
+        fn notifying_rolls_the_undistributed_rewards_over() {
+            let mut contract = new_contract_with_extra_rewards(1_000, 100);
+            let token = AccountId::from(EXTRA_TOKEN);
 
-    impl StakingContract {
-        #[ink(constructor)]
-        pub fn new(staking_token: AccountId, reputation_token: AccountId) -> Self {
-            let mut instance = StakingContract {
-                staking: Default::default(),
-                reputation: Default::default(),
-            };
+            set_time(50);
+            contract.add_extra_rewards(token, 1_000, 100).unwrap();
 
-            instance.staking.staking_token = staking_token;
-            instance.staking.reward_rate = INITIAL_REWARD_RATE;
-            instance.staking.last_update_time = instance.env().block_timestamp();
-            instance.reputation.reputation_token = reputation_token;
-            instance
+            let state = contract.reward_state(token).unwrap();
+            assert_eq!(state.reward_rate, 15);
+            assert_eq!(state.period_finish, 150);
+            assert_eq!(state.reward_reserve, 2_000);
         }
 
-        #[ink(message)]
-        pub fn claim_reputation(&mut self) -> Result<(), StakingError> {
-            self.update_reputation(self.env().caller())?;
-            Ok(())
-        }
-    }
+        #[ink::test]
+        fn reward_tokens_are_added_once_and_up_to_the_maximum() {
+            let mut contract = new_contract();
+            let reward_token = contract.staking.reward_token;
 
-    #[cfg(test)]
-    mod tests {
-        use super::*;
-        use ink::codegen::Env;
-        use staking_token::token::StakingTokenContract;
+            assert_eq!(
+                contract.add_reward_token(reward_token),
+                Err(StakingError::RewardTokenAlreadyAdded)
+            );
 
-        const INITIAL_SUPPLY: Balance = 1_000_000_000 * 10u128.pow(18);
+            let tokens: Vec<AccountId> = (0..MAX_REWARD_TOKENS as u8)
+                .map(|i| AccountId::from([0x10 + i; 32]))
+                .collect();
+            for token in &tokens {
+                assert_eq!(contract.add_reward_token(*token), Ok(()));
+            }
+            assert_eq!(contract.reward_tokens(), tokens);
+
+            assert_eq!(
+                contract.add_reward_token(tokens[0]),
+                Err(StakingError::RewardTokenAlreadyAdded)
+            );
+            assert_eq!(
+                contract.add_reward_token(AccountId::from(EXTRA_TOKEN)),
+                Err(StakingError::TooManyRewardTokens)
+            );
+            assert_eq!(
+                contract.notify_reward_amount(AccountId::from(EXTRA_TOKEN), 1_000, 100),
+                Err(StakingError::UnknownRewardToken)
+            );
+            assert_eq!(
+                contract.notify_reward_amount(tokens[0], 1_000, 0),
+                Err(StakingError::ZeroAmount)
+            );
+        }
 
         #[ink::test]
-        fn instantiation() {
-            let name = Some(openbrush::traits::String::from("My Staking Token"));
-            let symbol = Some(openbrush::traits::String::from("MST"));
-            let staking_token =
-                StakingTokenContract::new(name.clone(), symbol.clone(), 18, INITIAL_SUPPLY);
+        fn only_managers_and_distributors_can_set_up_extra_rewards() {
+            let mut contract = new_contract();
+            let bob = AccountId::from([0x4; 32]);
+            let token = AccountId::from(EXTRA_TOKEN);
 
-            let reputation_token = AccountId::from([0x1; 32]);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(bob);
 
-            let staking_contract =
-                StakingContract::new(staking_token.env().account_id(), reputation_token);
-            assert_eq!(staking_contract.staking.total_staked, 0);
-            assert_eq!(staking_contract.staking.reward_rate, 50);
-            assert_eq!(staking_contract.staking.reward_per_token_stored, 0);
-            assert_eq!(staking_contract.staking.last_update_time, 0);
             assert_eq!(
-                staking_contract.staking.staking_token,
-                staking_token.env().account_id()
+                contract.add_reward_token(token),
+                Err(StakingError::AccessControlError(
+                    AccessControlError::MissingRole
+                ))
+            );
+            assert_eq!(
+                contract.notify_reward_amount(token, 1_000, 100),
+                Err(StakingError::AccessControlError(
+                    AccessControlError::MissingRole
+                ))
             );
         }
 
+        #[ink::test]
+        fn extra_rewards_released_before_anyone_stakes_are_not_lost() {
+            let mut contract = new_contract_with_extra_rewards(1_000, 100);
+            let token = AccountId::from(EXTRA_TOKEN);
+            let alice = AccountId::from([0x3; 32]);
+
+            set_time(50);
+            stake(&mut contract, alice, 100);
+
+            // The 500 tokens released to nobody are released again over the rest of the period.
+            assert_eq!(contract.reward_state(token).unwrap().reward_rate, 20);
+            set_time(100);
+            assert_eq!(contract.extra_earned(token, alice), Ok(1_000));
+        }
+
+        #[ink::test]
+        fn extra_rewards_released_to_nobody_wait_for_the_next_distribution_once_finished() {
+            let mut state = RewardState::default();
+            state.notify(1_000, 100, 0).unwrap();
+
+            state.update(0, 150).unwrap();
+            assert_eq!((state.undistributed, state.last_update_time), (1_000, 100));
+
+            state.notify(1_000, 100, 150).unwrap();
+            assert_eq!((state.reward_rate, state.undistributed), (20, 0));
+            assert_eq!(state.reward_reserve, 2_000);
+        }
     }
 
     #[cfg(all(test, feature = "e2e-tests"))]
@@ -221,6 +2684,114 @@ pub mod staking {
         /// The End-to-End test `Result` type.
         type E2EResult<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
+        type E2EClient = ink_e2e::Client<ink_e2e::PolkadotConfig, ink::env::DefaultEnvironment>;
+        type Event = <StakingContract as ink::reflect::ContractEventBase>::Type;
+
+        /// Instantiates the staking token, the reputation token and a staking contract allowed
+        /// to mint reputation, and funds the rewards with 70% of the staking tokens. Returns the
+        /// staking token and the staking contract.
+        async fn deploy(client: &mut E2EClient) -> (AccountId, AccountId) {
+            let staking_token = client
+                .instantiate(
+                    "staking_token",
+                    &ink_e2e::alice(),
+                    StakingTokenContractRef::new(
+                        Some(openbrush::traits::String::from("My Staking Token")),
+                        Some(openbrush::traits::String::from("MST")),
+                        18,
+                        INITIAL_SUPPLY,
+                    ),
+                    0,
+                    None,
+                )
+                .await
+                .expect("instantiate failed")
+                .account_id;
+
+            let reputation_token = client
+                .instantiate(
+                    "reputation_token",
+                    &ink_e2e::alice(),
+                    ReputationTokenContractRef::new(),
+                    0,
+                    None,
+                )
+                .await
+                .expect("instantiate failed")
+                .account_id;
+
+            let staking_contract = client
+                .instantiate(
+                    "staking_contract",
+                    &ink_e2e::alice(),
+                    StakingContractRef::new(staking_token, staking_token, reputation_token),
+                    0,
+                    None,
+                )
+                .await
+                .expect("instantiate failed")
+                .account_id;
+
+            let grant_minter_role =
+                build_message::<ReputationTokenContractRef>(reputation_token.clone())
+                    .call(|contract| contract.set_minter(staking_contract.clone()));
+            client
+                .call(&ink_e2e::alice(), grant_minter_role, 0, None)
+                .await
+                .expect("grant_minter_role failed");
+
+            let rewards = INITIAL_SUPPLY * STAKING_ALLOCATION / 100;
+            let approve = build_message::<StakingTokenContractRef>(staking_token.clone())
+                .call(|contract| contract.approve(staking_contract.clone(), rewards));
+            client
+                .call(&ink_e2e::alice(), approve, 0, None)
+                .await
+                .expect("approve failed");
+
+            let fund_rewards = build_message::<StakingContractRef>(staking_contract.clone())
+                .call(|contract| contract.fund_rewards(rewards));
+            client
+                .call(&ink_e2e::alice(), fund_rewards, 0, None)
+                .await
+                .expect("fund_rewards failed");
+
+            (staking_token, staking_contract)
+        }
+
+        /// Returns the events emitted by `contract` during a call, with their number of topics.
+        fn emitted_events<V>(
+            result: &ink_e2e::CallResult<ink_e2e::PolkadotConfig, ink::env::DefaultEnvironment, V>,
+            contract: AccountId,
+        ) -> Vec<(Event, usize)> {
+            result
+                .events
+                .iter()
+                .map(|event| event.expect("encountered an invalid event"))
+                .filter(|event| {
+                    event.pallet_name() == "Contracts" && event.variant_name() == "ContractEmitted"
+                })
+                .filter_map(|event| {
+                    let (emitter, data) =
+                        <(AccountId, Vec<u8>) as scale::Decode>::decode(&mut event.field_bytes())
+                            .expect("encountered an invalid ContractEmitted event");
+                    (emitter == contract).then(|| {
+                        let decoded = <Event as scale::Decode>::decode(&mut &data[..])
+                            .expect("encountered invalid contract event data buffer");
+                        (decoded, event.topics().len())
+                    })
+                })
+                .collect()
+        }
+
+        async fn balance_of(client: &mut E2EClient, token: AccountId, owner: AccountId) -> Balance {
+            let balance_of = build_message::<StakingTokenContractRef>(token)
+                .call(|contract| contract.balance_of(owner));
+            client
+                .call_dry_run(&ink_e2e::alice(), &balance_of, 0, None)
+                .await
+                .return_value()
+        }
+
         /// We test that we can upload and instantiate the contract using its constructor.
         #[ink_e2e::test(
             additional_contracts = "../staking_token/Cargo.toml ../reputation_token/Cargo.toml"
@@ -260,7 +2831,7 @@ pub mod staking {
                 .instantiate(
                     "staking_contract",
                     &ink_e2e::alice(),
-                    StakingContractRef::new(staking_token, reputation_token),
+                    StakingContractRef::new(staking_token, staking_token, reputation_token),
                     0,
                     None,
                 )
@@ -333,7 +2904,7 @@ pub mod staking {
                 .instantiate(
                     "staking_contract",
                     &ink_e2e::alice(),
-                    StakingContractRef::new(staking_token, reputation_token),
+                    StakingContractRef::new(staking_token, staking_token, reputation_token),
                     0,
                     None,
                 )
@@ -470,6 +3041,37 @@ pub mod staking {
                 500_000
             );
 
+            // Alice stakes 250_000 tokens on behalf of Bob
+            let approve = build_message::<StakingTokenContractRef>(staking_token.clone())
+                .call(|contract| contract.approve(staking_contract.clone(), 250_000));
+            client
+                .call(&ink_e2e::alice(), approve, 0, None)
+                .await
+                .expect("approve failed");
+
+            let stake_for = build_message::<StakingContractRef>(staking_contract.clone())
+                .call(|contract| contract.stake_for(bob_account, 250_000));
+            client
+                .call(&ink_e2e::alice(), stake_for, 0, None)
+                .await
+                .expect("stake_for failed");
+
+            // The tokens are credited to Bob, not to Alice
+            assert_eq!(
+                client
+                    .call_dry_run(&ink_e2e::alice(), &bob_staked_amount, 0, None)
+                    .await
+                    .return_value(),
+                750_000
+            );
+            assert_eq!(
+                client
+                    .call_dry_run(&ink_e2e::alice(), &alice_staked_amount, 0, None)
+                    .await
+                    .return_value(),
+                1_000_000
+            );
+
             Ok(())
         }
 
@@ -512,7 +3114,7 @@ pub mod staking {
                 .instantiate(
                     "staking_contract",
                     &ink_e2e::alice(),
-                    StakingContractRef::new(staking_token, reputation_token),
+                    StakingContractRef::new(staking_token, staking_token, reputation_token),
                     0,
                     None,
                 )
@@ -667,7 +3269,7 @@ pub mod staking {
                 .instantiate(
                     "staking_contract",
                     &ink_e2e::alice(),
-                    StakingContractRef::new(staking_token, reputation_token),
+                    StakingContractRef::new(staking_token, staking_token, reputation_token),
                     0,
                     None,
                 )
@@ -685,20 +3287,25 @@ pub mod staking {
                 .await
                 .expect("grant_minter_role failed");
 
-            // Transfer 70% of the staking tokens to the staking contract
-            let transfer =
+            // Fund the rewards with 70% of the staking tokens
+            let approve =
                 build_message::<StakingTokenContractRef>(staking_token.clone()).call(|contract| {
-                    contract.transfer(
+                    contract.approve(
                         staking_contract.clone(),
                         INITIAL_SUPPLY * STAKING_ALLOCATION / 100,
-                        vec![],
                     )
                 });
+            client
+                .call(&ink_e2e::alice(), approve, 0, None)
+                .await
+                .expect("approve failed");
 
+            let fund_rewards = build_message::<StakingContractRef>(staking_contract.clone())
+                .call(|contract| contract.fund_rewards(INITIAL_SUPPLY * STAKING_ALLOCATION / 100));
             client
-                .call(&ink_e2e::alice(), transfer, 0, None)
+                .call(&ink_e2e::alice(), fund_rewards, 0, None)
                 .await
-                .expect("transfer failed");
+                .expect("fund_rewards failed");
 
             // Alice allows the staking contract to spend 100_000_000^18 tokens on her behalf
             let approve =
@@ -719,7 +3326,9 @@ pub mod staking {
                 .expect("stake failed");
 
             // TODO How to simulate elapsed time here ? Does this work ?
-            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(HALVING_PERIOD);
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(
+                emission::ERA_DURATION,
+            );
             ink::env::test::advance_block::<ink::env::DefaultEnvironment>();
 
             // Get Reward for Alice
@@ -744,5 +3353,155 @@ pub mod staking {
 
             Ok(())
         }
+
+        #[ink_e2e::test(
+            additional_contracts = "../staking_token/Cargo.toml ../reputation_token/Cargo.toml"
+        )]
+        async fn staking_messages_emit_events_with_the_staker_as_topic(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            let (staking_token, staking_contract) = deploy(&mut client).await;
+            let alice = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+
+            let approve = build_message::<StakingTokenContractRef>(staking_token.clone())
+                .call(|contract| contract.approve(staking_contract.clone(), 1_000_000));
+            client
+                .call(&ink_e2e::alice(), approve, 0, None)
+                .await
+                .expect("approve failed");
+
+            let stake = build_message::<StakingContractRef>(staking_contract.clone())
+                .call(|contract| contract.stake(1_000_000));
+            let result = client
+                .call(&ink_e2e::alice(), stake, 0, None)
+                .await
+                .expect("stake failed");
+            let position = match emitted_events(&result, staking_contract)
+                .into_iter()
+                .find(|(event, _)| matches!(event, Event::Staked(_)))
+            {
+                Some((Event::Staked(event), 2)) => {
+                    assert_eq!((event.staker, event.amount), (alice, 1_000_000));
+                    event.position
+                }
+                _ => panic!("expected a Staked event"),
+            };
+
+            let withdraw = build_message::<StakingContractRef>(staking_contract.clone())
+                .call(|contract| contract.withdraw(400_000));
+            let result = client
+                .call(&ink_e2e::alice(), withdraw, 0, None)
+                .await
+                .expect("withdraw failed");
+            match emitted_events(&result, staking_contract)
+                .into_iter()
+                .find(|(event, _)| matches!(event, Event::Withdrawn(_)))
+            {
+                Some((Event::Withdrawn(event), 2)) => {
+                    assert_eq!(
+                        (event.staker, event.position, event.amount),
+                        (alice, position, 400_000)
+                    )
+                }
+                _ => panic!("expected a Withdrawn event"),
+            }
+
+            // Rewards accrue with the blocks produced since Alice staked.
+            let balance = balance_of(&mut client, staking_token, alice).await;
+            let get_reward = build_message::<StakingContractRef>(staking_contract.clone())
+                .call(|contract| contract.get_reward());
+            let result = client
+                .call(&ink_e2e::alice(), get_reward, 0, None)
+                .await
+                .expect("get_reward failed");
+            let reward = match emitted_events(&result, staking_contract)
+                .into_iter()
+                .find(|(event, _)| matches!(event, Event::RewardPaid(_)))
+            {
+                Some((Event::RewardPaid(event), 2)) => {
+                    assert_eq!(event.staker, alice);
+                    event.reward
+                }
+                _ => panic!("expected a RewardPaid event"),
+            };
+            assert!(reward > 0);
+            assert_eq!(
+                balance_of(&mut client, staking_token, alice).await,
+                balance + reward
+            );
+
+            Ok(())
+        }
+
+        #[ink_e2e::test(
+            additional_contracts = "../staking_token/Cargo.toml ../reputation_token/Cargo.toml"
+        )]
+        async fn exit_keeps_the_locked_positions(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            let (staking_token, staking_contract) = deploy(&mut client).await;
+            let alice = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+
+            let approve = build_message::<StakingTokenContractRef>(staking_token.clone())
+                .call(|contract| contract.approve(staking_contract.clone(), 1_500_000));
+            client
+                .call(&ink_e2e::alice(), approve, 0, None)
+                .await
+                .expect("approve failed");
+
+            let stake = build_message::<StakingContractRef>(staking_contract.clone())
+                .call(|contract| contract.stake(1_000_000));
+            client
+                .call(&ink_e2e::alice(), stake, 0, None)
+                .await
+                .expect("stake failed");
+
+            let stake_locked = build_message::<StakingContractRef>(staking_contract.clone())
+                .call(|contract| contract.stake_locked(500_000, 12 * 30 * 24 * 60 * 60 * 1000));
+            client
+                .call(&ink_e2e::alice(), stake_locked, 0, None)
+                .await
+                .expect("stake_locked failed");
+
+            let balance = balance_of(&mut client, staking_token, alice).await;
+            let exit = build_message::<StakingContractRef>(staking_contract.clone())
+                .call(|contract| contract.exit());
+            client
+                .call(&ink_e2e::alice(), exit, 0, None)
+                .await
+                .expect("exit failed");
+
+            // The flexible position is withdrawn along with the rewards of both positions.
+            let staked = build_message::<StakingContractRef>(staking_contract.clone())
+                .call(|contract| contract.balance_of(alice));
+            assert_eq!(
+                client
+                    .call_dry_run(&ink_e2e::alice(), &staked, 0, None)
+                    .await
+                    .return_value(),
+                500_000
+            );
+            assert!(balance_of(&mut client, staking_token, alice).await > balance + 1_000_000);
+
+            // The locked position keeps earning once a later block is produced by another call.
+            let approve = build_message::<StakingTokenContractRef>(staking_token.clone())
+                .call(|contract| contract.approve(staking_contract.clone(), 0));
+            client
+                .call(&ink_e2e::alice(), approve, 0, None)
+                .await
+                .expect("approve failed");
+
+            let pending_reward = build_message::<StakingContractRef>(staking_contract.clone())
+                .call(|contract| contract.pending_reward(alice));
+            assert!(matches!(
+                client
+                    .call_dry_run(&ink_e2e::alice(), &pending_reward, 0, None)
+                    .await
+                    .return_value(),
+                Ok(reward) if reward > 0
+            ));
+
+            Ok(())
+        }
     }
 }
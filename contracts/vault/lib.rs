@@ -0,0 +1,539 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+#![feature(min_specialization)]
+
+/// A PSP-22 vault staking its deposits in a staking contract and compounding the rewards, so
+/// that every share is worth a growing amount of staking tokens.
+#[openbrush::contract]
+pub mod vault {
+    use openbrush::{
+        contracts::psp22::extensions::metadata::*,
+        traits::{self, Storage},
+    };
+    use staking_dapp::{
+        impls::vault,
+        traits::vault::{Internal, *},
+    };
+
+    /// Emitted when staking tokens are deposited for shares.
+    #[ink(event)]
+    pub struct Deposit {
+        #[ink(topic)]
+        owner: AccountId,
+        assets: Balance,
+        shares: Balance,
+    }
+
+    /// Emitted when shares are redeemed for staking tokens.
+    #[ink(event)]
+    pub struct Redeem {
+        #[ink(topic)]
+        owner: AccountId,
+        assets: Balance,
+        shares: Balance,
+    }
+
+    #[ink(storage)]
+    #[derive(Default, Storage)]
+    pub struct VaultContract {
+        #[storage_field]
+        psp22: psp22::Data,
+        #[storage_field]
+        metadata: metadata::Data,
+        #[storage_field]
+        vault: vault::data::Data,
+    }
+
+    impl PSP22 for VaultContract {}
+
+    impl PSP22Metadata for VaultContract {}
+
+    impl Vault for VaultContract {}
+
+    impl Internal for VaultContract {
+        fn emit_deposit_event(&self, owner: AccountId, assets: Balance, shares: Balance) {
+            self.env().emit_event(Deposit {
+                owner,
+                assets,
+                shares,
+            });
+        }
+
+        fn emit_redeem_event(&self, owner: AccountId, assets: Balance, shares: Balance) {
+            self.env().emit_event(Redeem {
+                owner,
+                assets,
+                shares,
+            });
+        }
+    }
+
+    impl VaultContract {
+        /// Creates a new `VaultContract` instance staking `asset` in `staking_contract`, whose
+        /// shares have the given `name`, `symbol` and `decimals`. The staking contract must pay
+        /// its rewards in `asset` and have no unbonding period.
+        #[ink(constructor)]
+        pub fn new(
+            asset: AccountId,
+            staking_contract: AccountId,
+            name: Option<traits::String>,
+            symbol: Option<traits::String>,
+            decimals: u8,
+        ) -> Self {
+            let mut instance = Self::default();
+
+            instance.vault.asset = asset;
+            instance.vault.staking_contract = staking_contract;
+            instance.metadata.name = name;
+            instance.metadata.symbol = symbol;
+            instance.metadata.decimals = decimals;
+            instance
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use staking_dapp::impls::vault::shares;
+
+        fn new_vault() -> VaultContract {
+            VaultContract::new(
+                AccountId::from([0x1; 32]),
+                AccountId::from([0x2; 32]),
+                Some(traits::String::from("Vault My Staking Token")),
+                Some(traits::String::from("vMST")),
+                18,
+            )
+        }
+
+        #[ink::test]
+        fn constructor_sets_the_asset_and_staking_contract() {
+            let vault = new_vault();
+
+            assert_eq!(vault.asset(), AccountId::from([0x1; 32]));
+            assert_eq!(vault.staking_contract(), AccountId::from([0x2; 32]));
+            assert_eq!(vault.token_symbol(), Some(traits::String::from("vMST")));
+            assert_eq!(vault.total_supply(), 0);
+        }
+
+        #[ink::test]
+        fn zero_amounts_are_rejected() {
+            let mut vault = new_vault();
+
+            assert_eq!(vault.deposit(0), Err(VaultError::ZeroAmount));
+            assert_eq!(vault.redeem(0), Err(VaultError::ZeroAmount));
+        }
+
+        #[ink::test]
+        fn shares_are_minted_one_for_one_into_an_empty_vault() {
+            assert_eq!(shares::to_shares(1_000, 0, 0), Some(1_000));
+            assert_eq!(shares::to_assets(1_000, 0, 0), Some(1_000));
+        }
+
+        #[ink::test]
+        fn compounded_rewards_raise_the_value_of_every_share() {
+            // 1_000 shares backed by 1_000 deposited tokens and 1_000 compounded rewards, which
+            // with the virtual offset price a share at 1.5 tokens.
+            assert_eq!(shares::to_assets(1_000, 1_000, 2_000), Some(1_500));
+            assert_eq!(shares::to_assets(100, 1_000, 2_000), Some(150));
+            assert_eq!(shares::to_shares(150, 1_000, 2_000), Some(100));
+        }
+
+        #[ink::test]
+        fn conversions_round_down_in_favour_of_the_vault() {
+            assert_eq!(shares::to_shares(2, 1_000, 2_000), Some(1));
+            assert_eq!(shares::to_assets(1, 1_000, 2_000), Some(1));
+            assert_eq!(shares::to_shares(1, 1_000, 2_000), Some(0));
+        }
+
+        #[ink::test]
+        fn inflating_the_share_price_costs_more_than_it_takes() {
+            // The attacker deposits one token, then stakes a million for the vault.
+            let attacker_shares = shares::to_shares(1, 0, 0).unwrap();
+            let total_assets = 1 + 1_000_000;
+
+            // The next deposit still mints shares, and the donation mostly goes to the virtual
+            // shares instead of the attacker.
+            let victim_shares = shares::to_shares(10_000, attacker_shares, total_assets).unwrap();
+            assert!(victim_shares > 0);
+
+            let total_supply = attacker_shares + victim_shares;
+            let total_assets = total_assets + 10_000;
+            let redeemed = shares::to_assets(attacker_shares, total_supply, total_assets).unwrap();
+            assert!(redeemed < 1_000_001 / 100);
+        }
+    }
+
+    #[cfg(all(test, feature = "e2e-tests"))]
+    mod e2e_tests {
+        use super::*;
+        use ink_e2e::build_message;
+        use openbrush::contracts::psp22::psp22_external::PSP22;
+        use reputation_token::token::ReputationTokenContractRef;
+        use staking_contract::staking::StakingContractRef;
+        use staking_dapp::traits::{
+            staking::staking_external::Staking, vault::vault_external::Vault,
+        };
+        use staking_token::token::StakingTokenContractRef;
+
+        const INITIAL_SUPPLY: Balance = 1_000_000_000 * 10u128.pow(18);
+        const STAKING_ALLOCATION: u128 = 70;
+
+        /// The End-to-End test `Result` type.
+        type E2EResult<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+        type E2EClient = ink_e2e::Client<ink_e2e::PolkadotConfig, ink::env::DefaultEnvironment>;
+
+        /// Instantiates the staking token, the reputation token, a staking contract allowed to
+        /// mint reputation with 70% of the staking tokens as rewards, and a vault staking in it.
+        /// Returns the staking token, the staking contract and the vault.
+        async fn deploy(client: &mut E2EClient) -> (AccountId, AccountId, AccountId) {
+            let staking_token = client
+                .instantiate(
+                    "staking_token",
+                    &ink_e2e::alice(),
+                    StakingTokenContractRef::new(
+                        Some(traits::String::from("My Staking Token")),
+                        Some(traits::String::from("MST")),
+                        18,
+                        INITIAL_SUPPLY,
+                    ),
+                    0,
+                    None,
+                )
+                .await
+                .expect("instantiate failed")
+                .account_id;
+
+            let reputation_token = client
+                .instantiate(
+                    "reputation_token",
+                    &ink_e2e::alice(),
+                    ReputationTokenContractRef::new(),
+                    0,
+                    None,
+                )
+                .await
+                .expect("instantiate failed")
+                .account_id;
+
+            let staking_contract = client
+                .instantiate(
+                    "staking_contract",
+                    &ink_e2e::alice(),
+                    StakingContractRef::new(staking_token, staking_token, reputation_token),
+                    0,
+                    None,
+                )
+                .await
+                .expect("instantiate failed")
+                .account_id;
+
+            let vault = client
+                .instantiate(
+                    "vault",
+                    &ink_e2e::alice(),
+                    VaultContractRef::new(
+                        staking_token,
+                        staking_contract,
+                        Some(traits::String::from("Vault My Staking Token")),
+                        Some(traits::String::from("vMST")),
+                        18,
+                    ),
+                    0,
+                    None,
+                )
+                .await
+                .expect("instantiate failed")
+                .account_id;
+
+            // Let the staking contract mint reputation and fund its rewards.
+            let set_minter = build_message::<ReputationTokenContractRef>(reputation_token.clone())
+                .call(|contract| contract.set_minter(staking_contract.clone()));
+            client
+                .call(&ink_e2e::alice(), set_minter, 0, None)
+                .await
+                .expect("set_minter failed");
+
+            let rewards = INITIAL_SUPPLY * STAKING_ALLOCATION / 100;
+            let approve = build_message::<StakingTokenContractRef>(staking_token.clone())
+                .call(|contract| contract.approve(staking_contract.clone(), rewards));
+            client
+                .call(&ink_e2e::alice(), approve, 0, None)
+                .await
+                .expect("approve failed");
+
+            let fund_rewards = build_message::<StakingContractRef>(staking_contract.clone())
+                .call(|contract| contract.fund_rewards(rewards));
+            client
+                .call(&ink_e2e::alice(), fund_rewards, 0, None)
+                .await
+                .expect("fund_rewards failed");
+
+            (staking_token, staking_contract, vault)
+        }
+
+        #[ink_e2e::test(
+            additional_contracts = "../staking/Cargo.toml ../staking_token/Cargo.toml ../reputation_token/Cargo.toml"
+        )]
+        async fn compounded_rewards_are_redeemed_with_the_shares(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            let alice = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let bob = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+
+            let (staking_token, staking_contract, vault) = deploy(&mut client).await;
+
+            // Alice deposits into the empty vault, one share per token.
+            let deposit = 1_000_000 * 10u128.pow(18);
+            let approve = build_message::<StakingTokenContractRef>(staking_token.clone())
+                .call(|contract| contract.approve(vault.clone(), deposit));
+            client
+                .call(&ink_e2e::alice(), approve, 0, None)
+                .await
+                .expect("approve failed");
+
+            let alice_deposit = build_message::<VaultContractRef>(vault.clone())
+                .call(|vault| vault.deposit(deposit));
+            client
+                .call(&ink_e2e::alice(), alice_deposit, 0, None)
+                .await
+                .expect("deposit failed");
+
+            let alice_shares = build_message::<VaultContractRef>(vault.clone())
+                .call(|vault| vault.balance_of(alice));
+            assert_eq!(
+                client
+                    .call_dry_run(&ink_e2e::alice(), &alice_shares, 0, None)
+                    .await
+                    .return_value(),
+                deposit
+            );
+
+            let vault_stake = build_message::<StakingContractRef>(staking_contract.clone())
+                .call(|contract| contract.balance_of(vault));
+            assert_eq!(
+                client
+                    .call_dry_run(&ink_e2e::alice(), &vault_stake, 0, None)
+                    .await
+                    .return_value(),
+                deposit
+            );
+
+            // Harvesting stakes the rewards of the vault, which raises the price of a share.
+            let harvest =
+                build_message::<VaultContractRef>(vault.clone()).call(|vault| vault.harvest());
+            client
+                .call(&ink_e2e::alice(), harvest, 0, None)
+                .await
+                .expect("harvest failed");
+
+            assert!(
+                client
+                    .call_dry_run(&ink_e2e::alice(), &vault_stake, 0, None)
+                    .await
+                    .return_value()
+                    > deposit
+            );
+
+            let share_price = build_message::<VaultContractRef>(vault.clone())
+                .call(|vault| vault.convert_to_assets(deposit));
+            let price = client
+                .call_dry_run(&ink_e2e::alice(), &share_price, 0, None)
+                .await
+                .return_value();
+            assert!(price > deposit);
+
+            // Bob deposits the same amount after compounding and gets fewer shares.
+            let transfer = build_message::<StakingTokenContractRef>(staking_token.clone())
+                .call(|contract| contract.transfer(bob, deposit, vec![]));
+            client
+                .call(&ink_e2e::alice(), transfer, 0, None)
+                .await
+                .expect("transfer failed");
+
+            let approve = build_message::<StakingTokenContractRef>(staking_token.clone())
+                .call(|contract| contract.approve(vault.clone(), deposit));
+            client
+                .call(&ink_e2e::bob(), approve, 0, None)
+                .await
+                .expect("approve failed");
+
+            let bob_deposit = build_message::<VaultContractRef>(vault.clone())
+                .call(|vault| vault.deposit(deposit));
+            client
+                .call(&ink_e2e::bob(), bob_deposit, 0, None)
+                .await
+                .expect("deposit failed");
+
+            let bob_shares = build_message::<VaultContractRef>(vault.clone())
+                .call(|vault| vault.balance_of(bob));
+            assert!(
+                client
+                    .call_dry_run(&ink_e2e::alice(), &bob_shares, 0, None)
+                    .await
+                    .return_value()
+                    < deposit
+            );
+
+            // Alice redeems all her shares for her deposit and the rewards compounded since.
+            let alice_balance = build_message::<StakingTokenContractRef>(staking_token.clone())
+                .call(|contract| contract.balance_of(alice));
+            let balance = client
+                .call_dry_run(&ink_e2e::alice(), &alice_balance, 0, None)
+                .await
+                .return_value();
+
+            let redeem = build_message::<VaultContractRef>(vault.clone())
+                .call(|vault| vault.redeem(deposit));
+            let redeemed = client
+                .call(&ink_e2e::alice(), redeem, 0, None)
+                .await
+                .expect("redeem failed")
+                .return_value()
+                .expect("redeem failed");
+
+            assert!(redeemed >= price);
+            assert_eq!(
+                client
+                    .call_dry_run(&ink_e2e::alice(), &alice_balance, 0, None)
+                    .await
+                    .return_value(),
+                balance + redeemed
+            );
+            assert_eq!(
+                client
+                    .call_dry_run(&ink_e2e::alice(), &alice_shares, 0, None)
+                    .await
+                    .return_value(),
+                0
+            );
+
+            Ok(())
+        }
+
+        #[ink_e2e::test]
+        async fn redemptions_bear_the_withdrawal_fee(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            let alice = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let (staking_token, staking_contract, vault) = deploy(&mut client).await;
+
+            // A 2% fee on the tokens withdrawn within a week of their deposit, which is kept for
+            // the emission pool without a treasury.
+            let set_withdrawal_fee = build_message::<StakingContractRef>(staking_contract.clone())
+                .call(|contract| contract.set_withdrawal_fee(200, 7 * 24 * 60 * 60 * 1000));
+            client
+                .call(&ink_e2e::alice(), set_withdrawal_fee, 0, None)
+                .await
+                .expect("set_withdrawal_fee failed");
+
+            let deposit = 1_000_000 * 10u128.pow(18);
+            let approve = build_message::<StakingTokenContractRef>(staking_token.clone())
+                .call(|contract| contract.approve(vault.clone(), deposit));
+            client
+                .call(&ink_e2e::alice(), approve, 0, None)
+                .await
+                .expect("approve failed");
+
+            let alice_deposit = build_message::<VaultContractRef>(vault.clone())
+                .call(|vault| vault.deposit(deposit));
+            client
+                .call(&ink_e2e::alice(), alice_deposit, 0, None)
+                .await
+                .expect("deposit failed");
+
+            let alice_balance = build_message::<StakingTokenContractRef>(staking_token.clone())
+                .call(|contract| contract.balance_of(alice));
+            let balance = client
+                .call_dry_run(&ink_e2e::alice(), &alice_balance, 0, None)
+                .await
+                .return_value();
+
+            // The young deposit is charged, and Alice receives what the vault received.
+            let redeem = build_message::<VaultContractRef>(vault.clone())
+                .call(|vault| vault.redeem(deposit));
+            let redeemed = client
+                .call(&ink_e2e::alice(), redeem, 0, None)
+                .await
+                .expect("redeem failed")
+                .return_value()
+                .expect("redeem failed");
+
+            assert!(redeemed < deposit);
+            assert!(redeemed >= deposit - deposit * 2 / 100);
+            assert_eq!(
+                client
+                    .call_dry_run(&ink_e2e::alice(), &alice_balance, 0, None)
+                    .await
+                    .return_value(),
+                balance + redeemed
+            );
+
+            let vault_balance = build_message::<StakingTokenContractRef>(staking_token.clone())
+                .call(|contract| contract.balance_of(vault));
+            assert_eq!(
+                client
+                    .call_dry_run(&ink_e2e::alice(), &vault_balance, 0, None)
+                    .await
+                    .return_value(),
+                0
+            );
+
+            Ok(())
+        }
+
+        #[ink_e2e::test]
+        async fn redemptions_go_ahead_while_rewards_vest(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            let (staking_token, staking_contract, vault) = deploy(&mut client).await;
+
+            let deposit = 1_000_000 * 10u128.pow(18);
+            let approve = build_message::<StakingTokenContractRef>(staking_token.clone())
+                .call(|contract| contract.approve(vault.clone(), deposit));
+            client
+                .call(&ink_e2e::alice(), approve, 0, None)
+                .await
+                .expect("approve failed");
+
+            let alice_deposit = build_message::<VaultContractRef>(vault.clone())
+                .call(|vault| vault.deposit(deposit));
+            client
+                .call(&ink_e2e::alice(), alice_deposit, 0, None)
+                .await
+                .expect("deposit failed");
+
+            // Vesting rewards cannot be compounded, so they no longer count towards the shares.
+            let set_vesting = build_message::<StakingContractRef>(staking_contract.clone())
+                .call(|contract| contract.set_vesting(30 * 24 * 60 * 60 * 1000, 0));
+            client
+                .call(&ink_e2e::alice(), set_vesting, 0, None)
+                .await
+                .expect("set_vesting failed");
+
+            let total_assets =
+                build_message::<VaultContractRef>(vault.clone()).call(|vault| vault.total_assets());
+            assert_eq!(
+                client
+                    .call_dry_run(&ink_e2e::alice(), &total_assets, 0, None)
+                    .await
+                    .return_value(),
+                deposit
+            );
+
+            // The harvest fails, and the redemption goes ahead without it.
+            let redeem = build_message::<VaultContractRef>(vault.clone())
+                .call(|vault| vault.redeem(deposit));
+            let redeemed = client
+                .call(&ink_e2e::alice(), redeem, 0, None)
+                .await
+                .expect("redeem failed")
+                .return_value()
+                .expect("redeem failed");
+
+            assert_eq!(redeemed, deposit);
+
+            Ok(())
+        }
+    }
+}
@@ -1,2 +1,3 @@
 pub mod staking;
-pub mod reputation;
\ No newline at end of file
+pub mod reputation;
+pub mod vault;
\ No newline at end of file
@@ -12,6 +12,8 @@ pub struct Data {
     pub reputation_token: AccountId,
     /// Mapping from account to last update time of reputation
     pub reputation_last_update: Mapping<AccountId, Timestamp>,
+    /// Mapping from account to the reputation accrued but not minted yet, times 1e18.
+    pub reputation_accrued: Mapping<AccountId, u128>,
 }
 
 impl Default for Data {
@@ -19,6 +21,7 @@ impl Default for Data {
         Self {
             reputation_token: [0u8; 32].into(),
             reputation_last_update: Default::default(),
+            reputation_accrued: Default::default(),
         }
     }
 }
@@ -1,3 +1,15 @@
+use crate::{
+    impls::staking::{
+        emission,
+        extra_rewards::RewardState,
+        math,
+        position::{Position, PositionId, MAX_POSITIONS},
+        unbonding::Unlock,
+        vesting::Vesting,
+    },
+    traits::staking::StakingError,
+};
+use ink::prelude::vec::Vec;
 use openbrush::{
     storage::Mapping,
     traits::{AccountId, Balance, Timestamp},
@@ -5,38 +17,302 @@ use openbrush::{
 
 const STORAGE_KEY: u32 = openbrush::storage_unique_key!(Data);
 
+/// The basis points `withdrawal_fee` is expressed in.
+pub const FEE_SCALE: u16 = 10_000;
+
 #[derive(Debug)]
 #[openbrush::upgradeable_storage(STORAGE_KEY)]
 pub struct Data {
     /// The address of the staking token contract.
     pub staking_token: AccountId,
-    /// Reward Rate how much reward token will be distributed per second
-    pub reward_rate: Balance,
+    /// The address of the token contract the rewards are paid in. It can be the staking token.
+    pub reward_token: AccountId,
+    /// Reward tokens held by the contract that have not been paid out yet.
+    pub reward_reserve: Balance,
+    /// Reward tokens allocated to the halving emission schedule. Zero until emission starts.
+    pub emission_pool: Balance,
+    /// Withdrawal fees and forfeited rewards kept in the reserve without a treasury, added to
+    /// the emission pool the next time the schedule restarts.
+    pub undistributed_rewards: Balance,
+    /// Genesis timestamp of the emission schedule, eras are counted from it. Funding the
+    /// rewards moves it to the funding block.
+    pub emission_start: Timestamp,
+    /// Length of an emission era, after which the emission rate halves.
+    pub halving_period: Timestamp,
     /// Last Update timestamp
     pub last_update_time: Timestamp,
     /// Reward Per Token Stored Accumulated reward per token, times 1e18.
     pub reward_per_token_stored: Balance,
-    /// User Reward Per Token Paid
-    pub user_reward_per_token_paid: Mapping<AccountId, Balance>,
-    /// User Reward
-    pub rewards: Mapping<AccountId, Balance>,
     /// The sum of all staked amounts of all users.
     pub total_staked: Balance,
     /// The mapping from user addresses to their staked amounts.
     pub balances: Mapping<AccountId, Balance>,
+    /// The sum of the weights of all positions, the rewards are shared out by weight.
+    pub total_weighted: Balance,
+    /// The mapping from position ids to positions.
+    pub positions: Mapping<PositionId, Position>,
+    /// The mapping from user addresses to the ids of their positions, oldest first.
+    pub account_positions: Mapping<AccountId, Vec<PositionId>>,
+    /// The id of the next position to open.
+    pub next_position_id: PositionId,
+    /// How long unstaked tokens wait before they can be withdrawn. Zero lets stakers withdraw
+    /// right away.
+    pub unbonding_period: Timestamp,
+    /// The mapping from user addresses to their pending unstake requests, oldest first.
+    pub unlocks: Mapping<AccountId, Vec<Unlock>>,
+    /// The sum of all the tokens waiting in unstake requests.
+    pub total_unbonding: Balance,
+    /// The addresses of the extra reward token contracts, in the order they were added.
+    pub reward_tokens: Vec<AccountId>,
+    /// The mapping from extra reward tokens to their distribution.
+    pub reward_states: Mapping<AccountId, RewardState>,
+    /// The mapping from extra reward tokens and user addresses to the reward per token the
+    /// rewards of the user were last settled at.
+    pub user_reward_per_token_paid: Mapping<(AccountId, AccountId), Balance>,
+    /// The mapping from extra reward tokens and user addresses to the settled rewards of the
+    /// user.
+    pub user_rewards: Mapping<(AccountId, AccountId), Balance>,
+    /// The mapping from user addresses to the addresses their rewards are paid to, when they are
+    /// not paid to the users themselves.
+    pub reward_recipients: Mapping<AccountId, AccountId>,
+    /// The mapping from user addresses to the operators allowed to claim their rewards.
+    pub operators: Mapping<AccountId, AccountId>,
+    /// The address of the receipt token contract minting a share per staked token, if any.
+    pub receipt_token: Option<AccountId>,
+    /// How long claimed rewards vest before they can be released. Zero pays them right away.
+    pub vesting_period: Timestamp,
+    /// The share of the unvested rewards forfeited when releasing them early, in basis points.
+    /// Zero disables early releases.
+    pub early_exit_penalty: u16,
+    /// The address forfeited rewards and withdrawal fees are sent to. They are kept for the
+    /// emission pool when it is not set.
+    pub treasury: Option<AccountId>,
+    /// The mapping from user addresses to their vesting rewards.
+    pub vestings: Mapping<AccountId, Vesting>,
+    /// The sum of all the rewards vesting or vested and not released yet.
+    pub total_vesting: Balance,
+    /// The share of the tokens withdrawn less than `withdrawal_fee_period` after they were
+    /// deposited charged as a fee, in basis points.
+    pub withdrawal_fee: u16,
+    /// How long deposited tokens are charged a fee when withdrawn.
+    pub withdrawal_fee_period: Timestamp,
+    /// The smallest amount that can be staked at once.
+    pub min_stake: Balance,
+    /// The most an account can have staked, if capped.
+    pub max_stake_per_account: Option<Balance>,
+    /// The most all the accounts can have staked together, if capped.
+    pub max_total_staked: Option<Balance>,
 }
 
 impl Default for Data {
     fn default() -> Self {
         Self {
             staking_token: [0u8; 32].into(),
-            reward_rate: Balance::default(),
+            reward_token: [0u8; 32].into(),
+            reward_reserve: Balance::default(),
+            emission_pool: Balance::default(),
+            undistributed_rewards: Balance::default(),
+            emission_start: Timestamp::default(),
+            halving_period: emission::ERA_DURATION,
             last_update_time: Timestamp::default(),
             reward_per_token_stored: Balance::default(),
-            user_reward_per_token_paid: Default::default(),
-            rewards: Default::default(),
             total_staked: Balance::default(),
             balances: Default::default(),
+            total_weighted: Balance::default(),
+            positions: Default::default(),
+            account_positions: Default::default(),
+            next_position_id: PositionId::default(),
+            unbonding_period: Timestamp::default(),
+            unlocks: Default::default(),
+            total_unbonding: Balance::default(),
+            reward_tokens: Vec::new(),
+            reward_states: Default::default(),
+            user_reward_per_token_paid: Default::default(),
+            user_rewards: Default::default(),
+            reward_recipients: Default::default(),
+            operators: Default::default(),
+            receipt_token: None,
+            vesting_period: Timestamp::default(),
+            early_exit_penalty: 0,
+            treasury: None,
+            vestings: Default::default(),
+            total_vesting: Balance::default(),
+            withdrawal_fee: 0,
+            withdrawal_fee_period: Timestamp::default(),
+            min_stake: Balance::default(),
+            max_stake_per_account: None,
+            max_total_staked: None,
+        }
+    }
+}
+
+impl Data {
+    /// Returns the ids of the positions of the account, oldest first.
+    pub fn positions_of(&self, owner: &AccountId) -> Vec<PositionId> {
+        self.account_positions.get(owner).unwrap_or_default()
+    }
+
+    /// Returns the address the rewards of the account are paid to.
+    pub fn reward_recipient(&self, owner: &AccountId) -> AccountId {
+        self.reward_recipients.get(owner).unwrap_or(*owner)
+    }
+
+    /// Returns the fee charged on `amount` tokens withdrawn from `position` at `now`, and takes
+    /// the tokens charged off those of the position still charged a fee.
+    ///
+    /// Without a treasury, the fees are only charged when the rewards are paid in the staking
+    /// token, since they are kept for the emission pool.
+    pub fn withdrawal_fee(
+        &self,
+        position: &mut Position,
+        amount: Balance,
+        now: Timestamp,
+    ) -> Result<Balance, StakingError> {
+        let liable = position.fee_liable_at(now, self.withdrawal_fee_period);
+        let charged = amount.min(liable);
+        position.fee_liable = liable - charged;
+
+        if self.withdrawal_fee == 0
+            || (self.treasury.is_none() && self.reward_token != self.staking_token)
+        {
+            return Ok(0);
+        }
+
+        math::mul_div(charged, self.withdrawal_fee as u128, FEE_SCALE as u128)
+            .ok_or(StakingError::OverflowError)
+    }
+
+    /// Adds `amount` reward tokens held by the contract to the reserve, and keeps them for the
+    /// emission pool until the schedule restarts.
+    pub fn keep_for_emission(&mut self, amount: Balance) -> Result<(), StakingError> {
+        self.reward_reserve = self
+            .reward_reserve
+            .checked_add(amount)
+            .ok_or(StakingError::OverflowError)?;
+        self.undistributed_rewards = self
+            .undistributed_rewards
+            .checked_add(amount)
+            .ok_or(StakingError::OverflowError)?;
+        Ok(())
+    }
+
+    /// Makes sure `amount` new tokens can be staked for the account.
+    ///
+    /// Returns `StakingError::BelowMinimum` if the amount is less than `min_stake`, and
+    /// `StakingError::CapExceeded` if it would take the stake of the account or the total stake
+    /// over their cap.
+    pub fn ensure_stake_limits(
+        &self,
+        account: &AccountId,
+        amount: Balance,
+    ) -> Result<(), StakingError> {
+        if amount < self.min_stake {
+            return Err(StakingError::BelowMinimum);
+        }
+        if let Some(cap) = self.max_total_staked {
+            let total = self
+                .total_staked
+                .checked_add(amount)
+                .ok_or(StakingError::OverflowError)?;
+            if total > cap {
+                return Err(StakingError::CapExceeded);
+            }
+        }
+        self.ensure_account_cap(account, amount)
+    }
+
+    /// Makes sure the stake of the account can grow by `amount` tokens.
+    ///
+    /// Returns `StakingError::CapExceeded` if it would take the stake of the account over
+    /// `max_stake_per_account`.
+    pub fn ensure_account_cap(
+        &self,
+        account: &AccountId,
+        amount: Balance,
+    ) -> Result<(), StakingError> {
+        if let Some(cap) = self.max_stake_per_account {
+            let balance = self
+                .balances
+                .get(account)
+                .unwrap_or(0)
+                .checked_add(amount)
+                .ok_or(StakingError::OverflowError)?;
+            if balance > cap {
+                return Err(StakingError::CapExceeded);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the sum of the weights of the positions of the account.
+    pub fn weight_of(&self, owner: &AccountId) -> Result<Balance, StakingError> {
+        self.positions_of(owner)
+            .iter()
+            .filter_map(|id| self.positions.get(id))
+            .try_fold(0, |weight: Balance, position| {
+                weight
+                    .checked_add(position.weight()?)
+                    .ok_or(StakingError::OverflowError)
+            })
+    }
+
+    /// Opens `position` and returns its id.
+    ///
+    /// Returns `StakingError::TooManyPositions` if its owner already has `MAX_POSITIONS`
+    /// positions.
+    pub fn open_position(&mut self, position: Position) -> Result<PositionId, StakingError> {
+        let mut ids = self.positions_of(&position.owner);
+        if ids.len() >= MAX_POSITIONS {
+            return Err(StakingError::TooManyPositions);
+        }
+
+        let id = self.next_position_id;
+        self.next_position_id = id.checked_add(1).ok_or(StakingError::OverflowError)?;
+
+        ids.push(id);
+        self.account_positions.insert(&position.owner, &ids);
+        self.set_position(id, position)?;
+        Ok(id)
+    }
+
+    /// Stores `position` and updates the balance of its owner and the totals to match. The
+    /// position is closed once it holds neither tokens nor rewards. The rewards of the position
+    /// must be settled beforehand.
+    pub fn set_position(&mut self, id: PositionId, position: Position) -> Result<(), StakingError> {
+        let (amount, weight) = match self.positions.get(&id) {
+            Some(old) => (old.amount, old.weight()?),
+            None => (0, 0),
+        };
+        let new_weight = position.weight()?;
+
+        self.total_staked = self
+            .total_staked
+            .checked_sub(amount)
+            .and_then(|total| total.checked_add(position.amount))
+            .ok_or(StakingError::OverflowError)?;
+        self.total_weighted = self
+            .total_weighted
+            .checked_sub(weight)
+            .and_then(|total| total.checked_add(new_weight))
+            .ok_or(StakingError::OverflowError)?;
+
+        let balance = self
+            .balances
+            .get(&position.owner)
+            .unwrap_or(0)
+            .checked_sub(amount)
+            .and_then(|balance| balance.checked_add(position.amount))
+            .ok_or(StakingError::OverflowError)?;
+        self.balances.insert(&position.owner, &balance);
+
+        if position.amount == 0 && position.rewards == 0 {
+            self.positions.remove(&id);
+            let mut ids = self.positions_of(&position.owner);
+            ids.retain(|position_id| *position_id != id);
+            self.account_positions.insert(&position.owner, &ids);
+        } else {
+            self.positions.insert(&id, &position);
         }
+        Ok(())
     }
 }
@@ -0,0 +1,72 @@
+use crate::traits::staking::StakingError;
+use openbrush::traits::{Balance, Timestamp};
+
+/// Default length of an emission era, after which the emission rate halves. Block timestamps
+/// are in milliseconds.
+pub const ERA_DURATION: Timestamp = 365 * 24 * 60 * 60 * 1000;
+
+/// Returns the amount of `pool` released over era `era`: half of the pool in the first era, a
+/// quarter in the second one, and so on.
+pub fn era_emission(pool: Balance, era: u64) -> Balance {
+    halve(pool, era.saturating_add(1))
+}
+
+/// Returns the amount of `pool` released by the halving schedule that started at `genesis`, up
+/// to the timestamp `at`, with eras lasting `era_duration`.
+///
+/// Each era releases its emission linearly, so the first `n` eras together release
+/// `pool - pool / 2^n` and the total never exceeds `pool`.
+pub fn emitted(
+    pool: Balance,
+    genesis: Timestamp,
+    era_duration: Timestamp,
+    at: Timestamp,
+) -> Result<Balance, StakingError> {
+    let elapsed = at.saturating_sub(genesis);
+    let era = elapsed
+        .checked_div(era_duration)
+        .ok_or(StakingError::DivideByZero)?;
+    let time_in_era = elapsed
+        .checked_rem(era_duration)
+        .ok_or(StakingError::DivideByZero)?;
+
+    let released = pool
+        .checked_sub(halve(pool, era))
+        .ok_or(StakingError::OverflowError)?;
+
+    let released_in_era = era_emission(pool, era)
+        .checked_mul(time_in_era as u128)
+        .ok_or(StakingError::OverflowError)?
+        .checked_div(era_duration as u128)
+        .ok_or(StakingError::DivideByZero)?;
+
+    released
+        .checked_add(released_in_era)
+        .ok_or(StakingError::OverflowError)
+}
+
+/// Returns the amount of `pool` released per millisecond at the timestamp `at` by the halving
+/// schedule that started at `genesis`, with eras lasting `era_duration`.
+pub fn emission_rate(
+    pool: Balance,
+    genesis: Timestamp,
+    era_duration: Timestamp,
+    at: Timestamp,
+) -> Result<Balance, StakingError> {
+    let era = at
+        .saturating_sub(genesis)
+        .checked_div(era_duration)
+        .ok_or(StakingError::DivideByZero)?;
+
+    era_emission(pool, era)
+        .checked_div(era_duration as u128)
+        .ok_or(StakingError::DivideByZero)
+}
+
+/// Divides `amount` by `2^times`, bottoming out at zero.
+fn halve(amount: Balance, times: u64) -> Balance {
+    u32::try_from(times)
+        .ok()
+        .and_then(|times| amount.checked_shr(times))
+        .unwrap_or(0)
+}
@@ -0,0 +1,126 @@
+use crate::{impls::staking::math, traits::staking::StakingError};
+use openbrush::traits::{Balance, Timestamp};
+
+/// How many extra reward tokens a pool can distribute, which bounds the cost of settling them on
+/// every balance change.
+pub const MAX_REWARD_TOKENS: usize = 8;
+
+/// The distribution of an extra reward token, released linearly until `period_finish`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, scale::Encode, scale::Decode)]
+#[cfg_attr(
+    feature = "std",
+    derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+)]
+pub struct RewardState {
+    /// Reward tokens released per millisecond until `period_finish`.
+    pub reward_rate: Balance,
+    /// When the current distribution ends.
+    pub period_finish: Timestamp,
+    /// When `reward_per_token_stored` was last updated.
+    pub last_update_time: Timestamp,
+    /// Accumulated reward per unit of weight, times 1e18.
+    pub reward_per_token_stored: Balance,
+    /// Reward tokens held by the contract that have not been paid out yet.
+    pub reward_reserve: Balance,
+    /// Rewards released while nobody was staked after the distribution finished, released with
+    /// the next one.
+    pub undistributed: Balance,
+}
+
+impl RewardState {
+    /// Returns the reward per unit of weight accumulated up to `now`, with `total_weighted`
+    /// sharing the rewards.
+    pub fn reward_per_token(
+        &self,
+        total_weighted: Balance,
+        now: Timestamp,
+    ) -> Result<Balance, StakingError> {
+        if total_weighted == 0 {
+            return Ok(self.reward_per_token_stored);
+        }
+
+        let elapsed = now
+            .min(self.period_finish)
+            .saturating_sub(self.last_update_time);
+
+        self.reward_rate
+            .checked_mul(elapsed as u128)
+            .and_then(|released| math::mul_div(released, 10u128.pow(18), total_weighted))
+            .and_then(|delta| self.reward_per_token_stored.checked_add(delta))
+            .ok_or(StakingError::OverflowError)
+    }
+
+    /// Stores the reward per unit of weight accumulated up to `now`. What was released while
+    /// nobody was staked is returned to the distribution rather than lost.
+    pub fn update(&mut self, total_weighted: Balance, now: Timestamp) -> Result<(), StakingError> {
+        if total_weighted == 0 {
+            let elapsed = now
+                .min(self.period_finish)
+                .saturating_sub(self.last_update_time);
+            let released = self
+                .reward_rate
+                .checked_mul(elapsed as u128)
+                .ok_or(StakingError::OverflowError)?;
+
+            self.last_update_time = now.min(self.period_finish);
+            return self.redistribute(released, now);
+        }
+
+        self.reward_per_token_stored = self.reward_per_token(total_weighted, now)?;
+        self.last_update_time = now.min(self.period_finish);
+        Ok(())
+    }
+
+    /// Adds `amount` to the distribution, which releases it along with what is left of the
+    /// current one over `duration` from `now`. The distribution must be updated beforehand.
+    pub fn notify(
+        &mut self,
+        amount: Balance,
+        duration: Timestamp,
+        now: Timestamp,
+    ) -> Result<(), StakingError> {
+        let leftover = self
+            .reward_rate
+            .checked_mul(self.period_finish.saturating_sub(now) as u128)
+            .and_then(|leftover| leftover.checked_add(self.undistributed))
+            .ok_or(StakingError::OverflowError)?;
+
+        self.undistributed = 0;
+        self.reward_rate = leftover
+            .checked_add(amount)
+            .ok_or(StakingError::OverflowError)?
+            .checked_div(duration as u128)
+            .ok_or(StakingError::DivideByZero)?;
+        self.period_finish = now
+            .checked_add(duration)
+            .ok_or(StakingError::OverflowError)?;
+        self.last_update_time = now;
+        self.reward_reserve = self
+            .reward_reserve
+            .checked_add(amount)
+            .ok_or(StakingError::OverflowError)?;
+        Ok(())
+    }
+
+    /// Returns `amount` rewards, still held in the reserve, to the distribution. They are
+    /// released along with what is left of it until `period_finish`, or with the next
+    /// distribution once it has finished.
+    fn redistribute(&mut self, amount: Balance, now: Timestamp) -> Result<(), StakingError> {
+        let remaining = self.period_finish.saturating_sub(now);
+        if remaining == 0 {
+            self.undistributed = self
+                .undistributed
+                .checked_add(amount)
+                .ok_or(StakingError::OverflowError)?;
+            return Ok(());
+        }
+
+        self.reward_rate = self
+            .reward_rate
+            .checked_mul(remaining as u128)
+            .and_then(|leftover| leftover.checked_add(amount))
+            .ok_or(StakingError::OverflowError)?
+            / remaining as u128;
+        Ok(())
+    }
+}
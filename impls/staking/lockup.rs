@@ -0,0 +1,18 @@
+use openbrush::traits::Timestamp;
+
+/// Reward multipliers are expressed in basis points.
+pub const MULTIPLIER_SCALE: u128 = 10_000;
+
+const MONTH: Timestamp = 30 * 24 * 60 * 60 * 1000;
+
+/// Returns the reward multiplier of tokens locked for `duration`: 1x without a lock-up, 1.5x from
+/// six months and 2x from twelve months.
+pub fn multiplier(duration: Timestamp) -> u128 {
+    if duration >= 12 * MONTH {
+        2 * MULTIPLIER_SCALE
+    } else if duration >= 6 * MONTH {
+        3 * MULTIPLIER_SCALE / 2
+    } else {
+        MULTIPLIER_SCALE
+    }
+}
@@ -0,0 +1,53 @@
+/// Computes `a * b / denominator` rounded down, using a 256-bit intermediate product so that
+/// only a quotient that does not fit in `u128` overflows.
+///
+/// Returns `None` if `denominator` is zero or the quotient overflows.
+pub fn mul_div(a: u128, b: u128, denominator: u128) -> Option<u128> {
+    if denominator == 0 {
+        return None;
+    }
+    if let Some(product) = a.checked_mul(b) {
+        return Some(product / denominator);
+    }
+
+    let (high, low) = full_mul(a, b);
+    if high >= denominator {
+        return None;
+    }
+
+    // Schoolbook long division of the 256-bit product, one bit at a time. The remainder stays
+    // below `denominator`, so it only needs the extra carry bit while shifting.
+    let mut remainder = high;
+    let mut quotient = 0u128;
+    for bit in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((low >> bit) & 1);
+        quotient <<= 1;
+        if carry == 1 || remainder >= denominator {
+            remainder = remainder.wrapping_sub(denominator);
+            quotient |= 1;
+        }
+    }
+
+    Some(quotient)
+}
+
+/// Returns the 256-bit product of `a` and `b` as `(high, low)` halves.
+fn full_mul(a: u128, b: u128) -> (u128, u128) {
+    const MASK: u128 = u64::MAX as u128;
+
+    let (a_high, a_low) = (a >> 64, a & MASK);
+    let (b_high, b_low) = (b >> 64, b & MASK);
+
+    let low_low = a_low * b_low;
+    let high_low = a_high * b_low;
+    let low_high = a_low * b_high;
+    let high_high = a_high * b_high;
+
+    let middle = (low_low >> 64) + (high_low & MASK) + (low_high & MASK);
+
+    let low = (middle << 64) | (low_low & MASK);
+    let high = high_high + (high_low >> 64) + (low_high >> 64) + (middle >> 64);
+
+    (high, low)
+}
@@ -1,2 +1,9 @@
 pub mod data;
-pub mod staking;
\ No newline at end of file
+pub mod emission;
+pub mod extra_rewards;
+pub mod lockup;
+pub mod math;
+pub mod position;
+pub mod staking;
+pub mod unbonding;
+pub mod vesting;
@@ -0,0 +1,100 @@
+use crate::{
+    impls::staking::{lockup, math},
+    traits::staking::StakingError,
+};
+use openbrush::traits::{AccountId, Balance, Timestamp};
+
+pub type PositionId = u64;
+
+/// How many positions an account can hold at once, which bounds the cost of the messages going
+/// through all of them.
+pub const MAX_POSITIONS: usize = 16;
+
+/// Tokens staked together, with their own start time, lock and reward checkpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, scale::Encode, scale::Decode)]
+#[cfg_attr(
+    feature = "std",
+    derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+)]
+pub struct Position {
+    /// The account the position belongs to.
+    pub owner: AccountId,
+    /// The amount of tokens staked in the position.
+    pub amount: Balance,
+    /// When the position was opened.
+    pub start_time: Timestamp,
+    /// The tokens cannot be withdrawn before this timestamp.
+    pub unlock_time: Timestamp,
+    /// How much the tokens weigh in the rewards sharing, in basis points.
+    pub multiplier: u128,
+    /// Reward per token already accounted for in `rewards`, times 1e18.
+    pub reward_per_token_paid: Balance,
+    /// Rewards earned by the position and not claimed yet.
+    pub rewards: Balance,
+    /// Tokens of the position charged a fee when withdrawn, until the withdrawal fee period has
+    /// passed since `deposit_time`. They are withdrawn first.
+    pub fee_liable: Balance,
+    /// When tokens charged a fee were last added to the position.
+    pub deposit_time: Timestamp,
+}
+
+impl Position {
+    /// Returns a position of `amount` tokens opened at `now` and locked for `duration`.
+    pub fn new(
+        owner: AccountId,
+        amount: Balance,
+        now: Timestamp,
+        duration: Timestamp,
+    ) -> Result<Self, StakingError> {
+        Ok(Self {
+            owner,
+            amount,
+            start_time: now,
+            unlock_time: now
+                .checked_add(duration)
+                .ok_or(StakingError::OverflowError)?,
+            multiplier: lockup::multiplier(duration),
+            reward_per_token_paid: 0,
+            rewards: 0,
+            fee_liable: amount,
+            deposit_time: now,
+        })
+    }
+
+    /// Returns whether the tokens of the position are still locked at `now`.
+    pub fn is_locked(&self, now: Timestamp) -> bool {
+        self.unlock_time > now
+    }
+
+    /// Returns the tokens of the position still charged a fee when withdrawn at `now`, with fees
+    /// charged for `period` after a deposit.
+    pub fn fee_liable_at(&self, now: Timestamp, period: Timestamp) -> Balance {
+        if now.saturating_sub(self.deposit_time) >= period {
+            return 0;
+        }
+        self.fee_liable
+    }
+
+    /// Adds `amount` tokens deposited at `now` to the tokens charged a fee, with fees charged for
+    /// `period` after a deposit. The tokens still charged restart their period along with them,
+    /// so that a top-up never shortens it.
+    pub fn add_fee_liable(
+        &mut self,
+        amount: Balance,
+        now: Timestamp,
+        period: Timestamp,
+    ) -> Result<(), StakingError> {
+        self.fee_liable = self
+            .fee_liable_at(now, period)
+            .checked_add(amount)
+            .ok_or(StakingError::OverflowError)?;
+        self.deposit_time = now;
+        Ok(())
+    }
+
+    /// Returns the weight of the position in the rewards sharing.
+    pub fn weight(&self) -> Result<Balance, StakingError> {
+        math::mul_div(self.amount, self.multiplier, lockup::MULTIPLIER_SCALE)
+            .ok_or(StakingError::OverflowError)
+    }
+}
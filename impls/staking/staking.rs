@@ -1,8 +1,22 @@
-use crate::{impls::staking::data, traits::staking::*};
+use crate::{
+    impls::staking::{
+        data, emission,
+        extra_rewards::RewardState,
+        lockup, math,
+        position::{Position, PositionId},
+        unbonding::{Unlock, MAX_UNLOCKS},
+        vesting::{Vesting, PENALTY_SCALE},
+    },
+    traits::staking::{Internal, *},
+};
 use ink::prelude::vec::Vec;
 use openbrush::{
-    contracts::traits::psp22::PSP22Ref,
-    traits::{AccountId, Balance, Storage},
+    contracts::{
+        pausable::{self, when_not_paused},
+        traits::psp22::PSP22Ref,
+    },
+    modifiers,
+    traits::{AccountId, Balance, Storage, Timestamp},
 };
 
 /// Macro ensures a condition is met, otherwise it returns an error.
@@ -16,103 +30,1091 @@ macro_rules! ensure {
 
 impl<T> Staking for T
 where
-    T: Storage<data::Data> + Internal,
+    T: Storage<data::Data> + Storage<pausable::Data> + Internal,
 {
+    #[modifiers(when_not_paused)]
     default fn stake(&mut self, amount: Balance) -> Result<(), StakingError> {
-        ensure!(amount > 0, StakingError::ZeroAmount);
+        open_position(self, Self::env().caller(), amount, 0)
+    }
 
-        let staker = Self::env().caller();
+    #[modifiers(when_not_paused)]
+    default fn stake_locked(
+        &mut self,
+        amount: Balance,
+        duration: Timestamp,
+    ) -> Result<(), StakingError> {
+        open_position(self, Self::env().caller(), amount, duration)
+    }
+
+    #[modifiers(when_not_paused)]
+    default fn stake_for(
+        &mut self,
+        beneficiary: AccountId,
+        amount: Balance,
+    ) -> Result<(), StakingError> {
+        let payer = Self::env().caller();
         let contract = Self::env().account_id();
-        let staking_token = self.data().staking_token;
+        let staking_token = self.data::<data::Data>().staking_token;
+
+        self.data::<data::Data>()
+            .ensure_stake_limits(&beneficiary, amount)?;
+        ensure_transferable(self, payer, amount)?;
+
+        let id = credit_stake(self, beneficiary, amount, true)?;
+
+        PSP22Ref::transfer_from(&staking_token, payer, contract, amount, Vec::<u8>::new())?;
+        self.mint_receipt(beneficiary, amount)?;
+
+        self.emit_staked_event(beneficiary, id, amount);
+        Ok(())
+    }
 
+    #[modifiers(when_not_paused)]
+    default fn withdraw(&mut self, amount: Balance) -> Result<(), StakingError> {
         ensure!(
-            PSP22Ref::allowance(&staking_token, staker, contract) >= amount,
-            StakingError::InsufficientAllowance
+            self.data::<data::Data>().unbonding_period == 0,
+            StakingError::UnbondingRequired
         );
 
+        let staker = Self::env().caller();
+        let staking_token = self.data::<data::Data>().staking_token;
+
+        let fee = withdraw_unlocked(self, staker, amount)?;
+        self.burn_receipt(staker, amount)?;
+
+        PSP22Ref::transfer(&staking_token, staker, amount - fee, Vec::<u8>::new())?;
+        charge_withdrawal_fee(self, staker, fee)?;
+
+        Ok(())
+    }
+
+    #[modifiers(when_not_paused)]
+    default fn withdraw_position(
+        &mut self,
+        id: PositionId,
+        amount: Balance,
+    ) -> Result<(), StakingError> {
+        ensure!(amount > 0, StakingError::ZeroAmount);
         ensure!(
-            PSP22Ref::balance_of(&staking_token, staker) >= amount,
-            StakingError::InsufficientBalance
+            self.data::<data::Data>().unbonding_period == 0,
+            StakingError::UnbondingRequired
         );
 
-        let new_amount = self
-            .data()
-            .balances
+        let staker = Self::env().caller();
+        let staking_token = self.data::<data::Data>().staking_token;
+        let position = owned_position(self, staker, id)?;
+
+        ensure!(position.amount >= amount, StakingError::InsufficientBalance);
+        ensure!(
+            !position.is_locked(Self::env().block_timestamp()),
+            StakingError::StakeLocked
+        );
+
+        // Settle rewards and reputation at the old balance before it changes.
+        let mut position = self.update_position_reward(id)?;
+        self.checkpoint_reputation(staker)?;
+
+        let fee = self.data::<data::Data>().withdrawal_fee(
+            &mut position,
+            amount,
+            Self::env().block_timestamp(),
+        )?;
+        position.amount -= amount;
+        self.data::<data::Data>().set_position(id, position)?;
+        self.burn_receipt(staker, amount)?;
+
+        PSP22Ref::transfer(&staking_token, staker, amount - fee, Vec::<u8>::new())?;
+        charge_withdrawal_fee(self, staker, fee)?;
+
+        self.emit_withdrawn_event(staker, id, amount);
+        Ok(())
+    }
+
+    #[modifiers(when_not_paused)]
+    default fn request_unstake(&mut self, amount: Balance) -> Result<(), StakingError> {
+        let staker = Self::env().caller();
+        let release_time = Self::env()
+            .block_timestamp()
+            .checked_add(self.data::<data::Data>().unbonding_period)
+            .ok_or(StakingError::OverflowError)?;
+
+        let mut unlocks = self
+            .data::<data::Data>()
+            .unlocks
             .get(&staker)
-            .unwrap_or(0)
+            .unwrap_or_default();
+        ensure!(unlocks.len() < MAX_UNLOCKS, StakingError::TooManyUnlocks);
+
+        let fee = withdraw_unlocked(self, staker, amount)?;
+        self.burn_receipt(staker, amount)?;
+
+        // The fee is charged on the request, so only the rest waits for the unbonding period.
+        let amount = amount - fee;
+        unlocks.push(Unlock {
+            amount,
+            release_time,
+        });
+        self.data::<data::Data>().unlocks.insert(&staker, &unlocks);
+        self.data::<data::Data>().total_unbonding = self
+            .data::<data::Data>()
+            .total_unbonding
             .checked_add(amount)
             .ok_or(StakingError::OverflowError)?;
+        charge_withdrawal_fee(self, staker, fee)?;
 
-        self.data().balances.insert(&staker, &new_amount);
-        self.data().total_staked = self
-            .data()
-            .total_staked
-            .checked_add(amount)
+        self.emit_unstake_requested_event(staker, amount, release_time);
+        Ok(())
+    }
+
+    default fn complete_unstake(&mut self) -> Result<(), StakingError> {
+        let staker = Self::env().caller();
+        let now = Self::env().block_timestamp();
+        let staking_token = self.data::<data::Data>().staking_token;
+
+        // Unbonding is lifted while the contract is paused, like the locks.
+        let paused = self.data::<pausable::Data>().paused;
+        let (released, pending): (Vec<Unlock>, Vec<Unlock>) = self
+            .data::<data::Data>()
+            .unlocks
+            .get(&staker)
+            .unwrap_or_default()
+            .into_iter()
+            .partition(|unlock| paused || unlock.release_time <= now);
+
+        let amount = released
+            .iter()
+            .try_fold(0 as Balance, |amount, unlock| {
+                amount.checked_add(unlock.amount)
+            })
             .ok_or(StakingError::OverflowError)?;
 
-        // self.update_reward(staker)?;
-        // self.update_reputation(staker)?;
-        PSP22Ref::transfer_from(&staking_token, staker, contract, amount, Vec::<u8>::new())?;
+        ensure!(amount > 0, StakingError::NothingToUnstake);
+
+        self.data::<data::Data>().unlocks.insert(&staker, &pending);
+        self.data::<data::Data>().total_unbonding = self
+            .data::<data::Data>()
+            .total_unbonding
+            .checked_sub(amount)
+            .ok_or(StakingError::OverflowError)?;
 
+        PSP22Ref::transfer(&staking_token, staker, amount, Vec::<u8>::new())?;
+
+        self.emit_unstake_completed_event(staker, amount);
         Ok(())
     }
 
-    default fn withdraw(&mut self, amount: Balance) -> Result<(), StakingError> {
-        ensure!(amount > 0, StakingError::ZeroAmount);
+    #[modifiers(when_not_paused)]
+    default fn cancel_unstake(&mut self) -> Result<(), StakingError> {
+        let staker = Self::env().caller();
+        let amount = self
+            .data::<data::Data>()
+            .unlocks
+            .get(&staker)
+            .unwrap_or_default()
+            .iter()
+            .try_fold(0 as Balance, |amount, unlock| {
+                amount.checked_add(unlock.amount)
+            })
+            .ok_or(StakingError::OverflowError)?;
+
+        ensure!(amount > 0, StakingError::NothingToUnstake);
+
+        self.data::<data::Data>().unlocks.remove(&staker);
+        self.data::<data::Data>().total_unbonding = self
+            .data::<data::Data>()
+            .total_unbonding
+            .checked_sub(amount)
+            .ok_or(StakingError::OverflowError)?;
+
+        // The tokens are already held by the contract, they only move back into a position. The
+        // fee was charged on the request, so they are not charged again.
+        let id = add_position(self, staker, amount, 0)?;
+        let position = self
+            .data::<data::Data>()
+            .positions
+            .get(&id)
+            .ok_or(StakingError::PositionNotFound)?;
+        self.data::<data::Data>().set_position(
+            id,
+            Position {
+                fee_liable: 0,
+                ..position
+            },
+        )?;
+        self.mint_receipt(staker, amount)?;
+
+        self.emit_staked_event(staker, id, amount);
+        Ok(())
+    }
 
+    default fn pending_unlocks(&self, staker: AccountId) -> Vec<Unlock> {
+        self.data::<data::Data>()
+            .unlocks
+            .get(&staker)
+            .unwrap_or_default()
+    }
+
+    #[modifiers(when_not_paused)]
+    default fn get_reward(&mut self) -> Result<(), StakingError> {
         let staker = Self::env().caller();
-        let staking_token = self.data().staking_token;
-        let staked_amount = self.data().balances.get(&staker).unwrap_or(0);
+        let ids = self.data::<data::Data>().positions_of(&staker);
 
-        ensure!(staked_amount >= amount, StakingError::InsufficientBalance);
+        pay_rewards(self, staker, &ids)
+    }
 
+    #[modifiers(when_not_paused)]
+    default fn get_rewards(&mut self) -> Result<(), StakingError> {
+        let staker = Self::env().caller();
+        let ids = self.data::<data::Data>().positions_of(&staker);
 
-        self.data().balances.insert(
-            &staker,
-            &(staked_amount
-                .checked_sub(amount)
-                .ok_or(StakingError::OverflowError)?),
+        pay_rewards(self, staker, &ids)?;
+        pay_extra_rewards(self, staker)
+    }
+
+    #[modifiers(when_not_paused)]
+    default fn claim_for(&mut self, staker: AccountId) -> Result<(), StakingError> {
+        let caller = Self::env().caller();
+        ensure!(
+            caller == staker || self.data::<data::Data>().operators.get(&staker) == Some(caller),
+            StakingError::NotOperator
         );
 
-        self.data().total_staked = self
-            .data()
-            .total_staked
+        let ids = self.data::<data::Data>().positions_of(&staker);
+
+        pay_rewards(self, staker, &ids)?;
+        pay_extra_rewards(self, staker)
+    }
+
+    default fn set_reward_recipient(
+        &mut self,
+        recipient: Option<AccountId>,
+    ) -> Result<(), StakingError> {
+        let staker = Self::env().caller();
+        // Setting the staker themselves resets the recipient, which the event reports as `None`.
+        let recipient = recipient.filter(|recipient| *recipient != staker);
+        match recipient {
+            Some(recipient) => self
+                .data::<data::Data>()
+                .reward_recipients
+                .insert(&staker, &recipient),
+            None => self.data::<data::Data>().reward_recipients.remove(&staker),
+        }
+
+        self.emit_reward_recipient_changed_event(staker, recipient);
+        Ok(())
+    }
+
+    default fn reward_recipient(&self, staker: AccountId) -> AccountId {
+        self.data::<data::Data>().reward_recipient(&staker)
+    }
+
+    default fn set_operator(&mut self, operator: Option<AccountId>) -> Result<(), StakingError> {
+        let staker = Self::env().caller();
+        match operator {
+            Some(operator) => self
+                .data::<data::Data>()
+                .operators
+                .insert(&staker, &operator),
+            None => self.data::<data::Data>().operators.remove(&staker),
+        }
+
+        self.emit_operator_changed_event(staker, operator);
+        Ok(())
+    }
+
+    default fn operator_of(&self, staker: AccountId) -> Option<AccountId> {
+        self.data::<data::Data>().operators.get(&staker)
+    }
+
+    #[modifiers(when_not_paused)]
+    default fn release_vested(&mut self) -> Result<(), StakingError> {
+        let staker = Self::env().caller();
+        let mut vesting = self
+            .data::<data::Data>()
+            .vestings
+            .get(&staker)
+            .unwrap_or_default();
+
+        let amount = vesting.release(Self::env().block_timestamp())?;
+        ensure!(amount > 0, StakingError::NothingVested);
+
+        if vesting.total()? == 0 {
+            self.data::<data::Data>().vestings.remove(&staker);
+        } else {
+            self.data::<data::Data>().vestings.insert(&staker, &vesting);
+        }
+        self.data::<data::Data>().total_vesting = self
+            .data::<data::Data>()
+            .total_vesting
             .checked_sub(amount)
             .ok_or(StakingError::OverflowError)?;
 
-        // self.update_reward(staker)?;
-        // self.update_reputation(staker)?;
-        PSP22Ref::transfer(&staking_token, staker, amount, Vec::<u8>::new())?;
+        PSP22Ref::transfer(
+            &self.data::<data::Data>().reward_token,
+            self.data::<data::Data>().reward_recipient(&staker),
+            amount,
+            Vec::<u8>::new(),
+        )?;
 
+        self.emit_reward_paid_event(staker, amount);
         Ok(())
     }
 
-    default fn get_reward(&mut self) -> Result<(), StakingError> {
+    #[modifiers(when_not_paused)]
+    default fn release_vested_early(&mut self) -> Result<(), StakingError> {
+        let penalty_rate = self.data::<data::Data>().early_exit_penalty;
+        ensure!(penalty_rate > 0, StakingError::EarlyExitDisabled);
+
         let staker = Self::env().caller();
+        let vesting = self
+            .data::<data::Data>()
+            .vestings
+            .get(&staker)
+            .ok_or(StakingError::NothingVested)?;
 
-        self.update_reward(staker)?;
+        let total = vesting.total()?;
+        let unvested = total - vesting.vested_at(Self::env().block_timestamp())?;
+        let penalty = math::mul_div(unvested, penalty_rate as u128, PENALTY_SCALE as u128)
+            .ok_or(StakingError::OverflowError)?;
+        let amount = total - penalty;
 
-        let rewards = self.data().rewards.get(&staker).unwrap_or(0);
-        if rewards > 0 {
-            self.data().rewards.insert(&staker, &0);
+        self.data::<data::Data>().vestings.remove(&staker);
+        self.data::<data::Data>().total_vesting = self
+            .data::<data::Data>()
+            .total_vesting
+            .checked_sub(total)
+            .ok_or(StakingError::OverflowError)?;
 
+        let reward_token = self.data::<data::Data>().reward_token;
+        if amount > 0 {
             PSP22Ref::transfer(
-                &self.data().staking_token,
-                staker,
-                rewards,
+                &reward_token,
+                self.data::<data::Data>().reward_recipient(&staker),
+                amount,
                 Vec::<u8>::new(),
             )?;
+            self.emit_reward_paid_event(staker, amount);
+        }
+
+        if penalty > 0 {
+            match self.data::<data::Data>().treasury {
+                Some(treasury) => {
+                    PSP22Ref::transfer(&reward_token, treasury, penalty, Vec::<u8>::new())?
+                }
+                None => self.data::<data::Data>().keep_for_emission(penalty)?,
+            }
+            self.emit_early_exit_penalty_event(staker, penalty);
+        }
+
+        Ok(())
+    }
+
+    default fn vested_amount(&self, account: AccountId) -> Result<Balance, StakingError> {
+        self.data::<data::Data>()
+            .vestings
+            .get(&account)
+            .map_or(Ok(0), |vesting| {
+                vesting.vested_at(Self::env().block_timestamp())
+            })
+    }
+
+    default fn vesting_of(&self, account: AccountId) -> Option<Vesting> {
+        self.data::<data::Data>().vestings.get(&account)
+    }
+
+    #[modifiers(when_not_paused)]
+    default fn compound(&mut self) -> Result<(), StakingError> {
+        ensure!(
+            self.data::<data::Data>().reward_token == self.data::<data::Data>().staking_token,
+            StakingError::RewardNotStakable
+        );
+        // Staking the rewards would release them before they vest.
+        ensure!(
+            self.data::<data::Data>().vesting_period == 0,
+            StakingError::RewardsVesting
+        );
+
+        let staker = Self::env().caller();
+        let ids = self.data::<data::Data>().positions_of(&staker);
+
+        let rewards = take_rewards(self, &ids)?;
+        ensure!(rewards > 0, StakingError::NoStakingRewards);
+
+        // Rewards could be claimed without a fee, so staking them does not add to it.
+        let id = credit_stake(self, staker, rewards, false)?;
+        self.mint_receipt(staker, rewards)?;
+
+        self.emit_reward_paid_event(staker, rewards);
+        self.emit_staked_event(staker, id, rewards);
+        Ok(())
+    }
+
+    #[modifiers(when_not_paused)]
+    default fn claim_position(&mut self, id: PositionId) -> Result<(), StakingError> {
+        let staker = Self::env().caller();
+        owned_position(self, staker, id)?;
+
+        pay_rewards(self, staker, &[id])
+    }
+
+    #[modifiers(when_not_paused)]
+    default fn exit(&mut self) -> Result<(), StakingError> {
+        ensure!(
+            self.data::<data::Data>().unbonding_period == 0,
+            StakingError::UnbondingRequired
+        );
+
+        let staker = Self::env().caller();
+        let staking_token = self.data::<data::Data>().staking_token;
+        // Locked positions stay staked, only their rewards are claimed.
+        let staked_amount = unlocked_balance(self, staker)?;
+
+        let fee = if staked_amount > 0 {
+            let fee = withdraw_unlocked(self, staker, staked_amount)?;
+            self.burn_receipt(staker, staked_amount)?;
+            fee
+        } else {
+            0
+        };
+
+        // Emptied positions are only kept for their rewards, so they are listed again.
+        let ids = self.data::<data::Data>().positions_of(&staker);
+        let rewards = take_rewards(self, &ids)?;
+
+        ensure!(
+            staked_amount > 0 || rewards > 0,
+            StakingError::InsufficientBalance
+        );
+
+        // Vesting rewards stay in the contract until they are released.
+        let rewards = if rewards > 0 && self.data::<data::Data>().vesting_period > 0 {
+            vest_rewards(self, staker, rewards)?;
+            0
+        } else {
+            rewards
+        };
+
+        charge_withdrawal_fee(self, staker, fee)?;
+        let staked_amount = staked_amount - fee;
+
+        let reward_token = self.data::<data::Data>().reward_token;
+        let recipient = self.data::<data::Data>().reward_recipient(&staker);
+        if reward_token == staking_token && recipient == staker {
+            let amount = staked_amount
+                .checked_add(rewards)
+                .ok_or(StakingError::OverflowError)?;
+            PSP22Ref::transfer(&staking_token, staker, amount, Vec::<u8>::new())?;
+        } else {
+            if staked_amount > 0 {
+                PSP22Ref::transfer(&staking_token, staker, staked_amount, Vec::<u8>::new())?;
+            }
+            if rewards > 0 {
+                PSP22Ref::transfer(&reward_token, recipient, rewards, Vec::<u8>::new())?;
+            }
+        }
+
+        if rewards > 0 {
+            self.emit_reward_paid_event(staker, rewards);
         }
         self.update_reputation(staker)?;
 
         Ok(())
     }
 
+    default fn emergency_withdraw(&mut self) -> Result<(), StakingError> {
+        let staker = Self::env().caller();
+        let staking_token = self.data::<data::Data>().staking_token;
+
+        // Locks and unbonding are only lifted while the contract is paused.
+        let paused = self.data::<pausable::Data>().paused;
+        ensure!(
+            paused || self.data::<data::Data>().unbonding_period == 0,
+            StakingError::UnbondingRequired
+        );
+
+        let amount = abandon_positions(self, staker, paused)?;
+
+        ensure!(amount > 0, StakingError::InsufficientBalance);
+
+        PSP22Ref::transfer(&staking_token, staker, amount, Vec::<u8>::new())?;
+
+        Ok(())
+    }
+
     default fn balance_of(&self, account: AccountId) -> Balance {
-        self.data().balances.get(&account).unwrap_or(0)
+        self.data::<data::Data>()
+            .balances
+            .get(&account)
+            .unwrap_or(0)
     }
 
     default fn total_staked(&self) -> Balance {
-        self.data().total_staked
+        self.data::<data::Data>().total_staked
+    }
+
+    default fn positions_of(&self, staker: AccountId) -> Vec<(PositionId, Position)> {
+        let data = self.data::<data::Data>();
+        data.positions_of(&staker)
+            .into_iter()
+            .filter_map(|id| data.positions.get(&id).map(|position| (id, position)))
+            .collect()
     }
+
+    #[modifiers(when_not_paused)]
+    default fn transfer_stake(
+        &mut self,
+        from: AccountId,
+        to: AccountId,
+        amount: Balance,
+    ) -> Result<(), StakingError> {
+        ensure!(
+            self.data::<data::Data>().receipt_token == Some(Self::env().caller()),
+            StakingError::NotReceiptToken
+        );
+
+        // The receipt token moves the shares themselves, only the stake behind them moves here.
+        // Moving stake is not a withdrawal, so no fee is charged, and the total stake is unchanged.
+        // The recipient receives it as a new deposit, so that moving fresh tokens to an account
+        // whose tokens are no longer charged does not avoid the fee.
+        self.data::<data::Data>().ensure_account_cap(&to, amount)?;
+        withdraw_unlocked(self, from, amount)?;
+        let id = credit_stake(self, to, amount, true)?;
+
+        self.emit_staked_event(to, id, amount);
+        Ok(())
+    }
+
+    #[modifiers(when_not_paused)]
+    default fn release_lock(&mut self, id: PositionId) -> Result<(), StakingError> {
+        let position = self
+            .data::<data::Data>()
+            .positions
+            .get(&id)
+            .ok_or(StakingError::PositionNotFound)?;
+
+        ensure!(
+            !position.is_locked(Self::env().block_timestamp()),
+            StakingError::StakeLocked
+        );
+
+        let mut position = self.update_position_reward(id)?;
+        position.multiplier = lockup::MULTIPLIER_SCALE;
+
+        self.data::<data::Data>().set_position(id, position)
+    }
+
+    default fn pending_reward(&self, account: AccountId) -> Result<Balance, StakingError> {
+        self.earned(account)
+    }
+
+    default fn compoundable_reward(&self, account: AccountId) -> Result<Balance, StakingError> {
+        let data = self.data::<data::Data>();
+        if data.reward_token != data.staking_token
+            || data.vesting_period > 0
+            || self.data::<pausable::Data>().paused
+        {
+            return Ok(0);
+        }
+
+        let rewards = self.earned(account)?;
+        if rewards > self.data::<data::Data>().reward_reserve {
+            return Ok(0);
+        }
+        Ok(rewards)
+    }
+
+    default fn pending_rewards(
+        &self,
+        account: AccountId,
+    ) -> Result<Vec<(AccountId, Balance)>, StakingError> {
+        let mut rewards = Vec::new();
+        rewards.push((
+            self.data::<data::Data>().reward_token,
+            self.earned(account)?,
+        ));
+
+        for token in self.data::<data::Data>().reward_tokens.clone() {
+            rewards.push((token, self.extra_earned(token, account)?));
+        }
+        Ok(rewards)
+    }
+
+    default fn reward_tokens(&self) -> Vec<AccountId> {
+        self.data::<data::Data>().reward_tokens.clone()
+    }
+
+    default fn reward_state(&self, token: AccountId) -> Option<RewardState> {
+        self.data::<data::Data>().reward_states.get(&token)
+    }
+
+    default fn reward_per_token(&self) -> Result<Balance, StakingError> {
+        Internal::reward_per_token(self)
+    }
+
+    default fn current_reward_rate(&self) -> Result<Balance, StakingError> {
+        let data = self.data::<data::Data>();
+        emission::emission_rate(
+            data.emission_pool,
+            data.emission_start,
+            data.halving_period,
+            Self::env().block_timestamp(),
+        )
+    }
+
+    default fn last_update_time(&self) -> Timestamp {
+        self.data::<data::Data>().last_update_time
+    }
+}
+
+/// Transfers `amount` tokens from the staker to the contract and stakes them in a new position
+/// locked for `duration`.
+fn open_position<T>(
+    instance: &mut T,
+    staker: AccountId,
+    amount: Balance,
+    duration: Timestamp,
+) -> Result<(), StakingError>
+where
+    T: Storage<data::Data> + Internal,
+{
+    let contract = T::env().account_id();
+    let staking_token = instance.data().staking_token;
+
+    instance.data().ensure_stake_limits(&staker, amount)?;
+    ensure_transferable(instance, staker, amount)?;
+
+    let id = add_position(instance, staker, amount, duration)?;
+
+    PSP22Ref::transfer_from(&staking_token, staker, contract, amount, Vec::<u8>::new())?;
+    instance.mint_receipt(staker, amount)?;
+
+    instance.emit_staked_event(staker, id, amount);
+    Ok(())
+}
+
+/// Opens a position of `amount` tokens locked for `duration` for the staker, settling the reward
+/// per token, their extra rewards and their reputation beforehand. The tokens must be
+/// transferred to the contract separately.
+///
+/// Returns the id of the position opened.
+pub fn add_position<T>(
+    instance: &mut T,
+    staker: AccountId,
+    amount: Balance,
+    duration: Timestamp,
+) -> Result<PositionId, StakingError>
+where
+    T: Storage<data::Data> + Internal,
+{
+    // Settle the reward per token and the reputation before the balances change.
+    instance.update_reward_per_token()?;
+    instance.update_extra_rewards(staker)?;
+    instance.checkpoint_reputation(staker)?;
+
+    let mut position = Position::new(staker, amount, T::env().block_timestamp(), duration)?;
+    position.reward_per_token_paid = instance.data().reward_per_token_stored;
+    instance.data().open_position(position)
+}
+
+/// Makes sure the contract can transfer `amount` staking tokens from `payer`.
+fn ensure_transferable<T>(
+    instance: &T,
+    payer: AccountId,
+    amount: Balance,
+) -> Result<(), StakingError>
+where
+    T: Storage<data::Data>,
+{
+    let staking_token = instance.data().staking_token;
+
+    ensure!(amount > 0, StakingError::ZeroAmount);
+    ensure!(
+        PSP22Ref::allowance(&staking_token, payer, T::env().account_id()) >= amount,
+        StakingError::InsufficientAllowance
+    );
+    ensure!(
+        PSP22Ref::balance_of(&staking_token, payer) >= amount,
+        StakingError::InsufficientBalance
+    );
+    Ok(())
+}
+
+/// Empties the unlocked positions of the staker, or all of them when `locks_lifted`, and returns
+/// the tokens they held. Nothing is settled: the pending rewards of the positions are dropped and
+/// stay in the reserve, and the reputation accrued on their tokens since the last checkpoint is
+/// lost. The tokens must be transferred out of the contract separately.
+pub fn abandon_positions<T>(
+    instance: &mut T,
+    staker: AccountId,
+    locks_lifted: bool,
+) -> Result<Balance, StakingError>
+where
+    T: Storage<data::Data> + Internal,
+{
+    let now = T::env().block_timestamp();
+
+    let mut amount: Balance = 0;
+    for id in instance.data().positions_of(&staker) {
+        let position = instance
+            .data()
+            .positions
+            .get(&id)
+            .ok_or(StakingError::PositionNotFound)?;
+        if position.amount == 0 || (!locks_lifted && position.is_locked(now)) {
+            continue;
+        }
+
+        let withdrawn = position.amount;
+        amount = amount
+            .checked_add(withdrawn)
+            .ok_or(StakingError::OverflowError)?;
+
+        instance.data().set_position(
+            id,
+            Position {
+                amount: 0,
+                rewards: 0,
+                ..position
+            },
+        )?;
+        instance.emit_withdrawn_event(staker, id, withdrawn);
+    }
+    Ok(amount)
+}
+
+/// Takes `amount` tokens out of the unlocked positions of the staker, newest first, settling
+/// their rewards and the reputation of the staker beforehand. The tokens must be transferred
+/// out of the contract separately.
+///
+/// Returns the withdrawal fee due on the tokens taken, which is left to the caller to charge.
+pub fn withdraw_unlocked<T>(
+    instance: &mut T,
+    staker: AccountId,
+    amount: Balance,
+) -> Result<Balance, StakingError>
+where
+    T: Storage<data::Data> + Internal,
+{
+    ensure!(amount > 0, StakingError::ZeroAmount);
+
+    let now = T::env().block_timestamp();
+    let staked_amount = instance.data().balances.get(&staker).unwrap_or(0);
+
+    ensure!(staked_amount >= amount, StakingError::InsufficientBalance);
+
+    let unlocked = unlocked_positions(instance, staker, now);
+
+    ensure!(
+        unlocked.iter().map(|(_, amount)| amount).sum::<Balance>() >= amount,
+        StakingError::StakeLocked
+    );
+
+    // Settle reputation at the old balance before it changes.
+    instance.checkpoint_reputation(staker)?;
+
+    let mut remaining = amount;
+    let mut fee: Balance = 0;
+    for (id, unlocked_amount) in unlocked {
+        if remaining == 0 {
+            break;
+        }
+
+        // Settle the rewards of the position at its old weight before it changes.
+        let mut position = instance.update_position_reward(id)?;
+        let withdrawn = remaining.min(unlocked_amount);
+        fee = fee
+            .checked_add(
+                instance
+                    .data()
+                    .withdrawal_fee(&mut position, withdrawn, now)?,
+            )
+            .ok_or(StakingError::OverflowError)?;
+        position.amount -= withdrawn;
+        remaining -= withdrawn;
+
+        instance.data().set_position(id, position)?;
+        instance.emit_withdrawn_event(staker, id, withdrawn);
+    }
+
+    Ok(fee)
+}
+
+/// Returns the ids and amounts of the unlocked positions of the staker, newest first.
+fn unlocked_positions<T>(
+    instance: &mut T,
+    staker: AccountId,
+    now: Timestamp,
+) -> Vec<(PositionId, Balance)>
+where
+    T: Storage<data::Data>,
+{
+    instance
+        .data()
+        .positions_of(&staker)
+        .into_iter()
+        .rev()
+        .filter_map(|id| {
+            instance
+                .data()
+                .positions
+                .get(&id)
+                .map(|position| (id, position))
+        })
+        .filter(|(_, position)| position.amount > 0 && !position.is_locked(now))
+        .map(|(id, position)| (id, position.amount))
+        .collect()
+}
+
+/// Returns the amount the staker can withdraw right away, outside of their locked positions.
+pub fn unlocked_balance<T>(instance: &mut T, staker: AccountId) -> Result<Balance, StakingError>
+where
+    T: Storage<data::Data>,
+{
+    unlocked_positions(instance, staker, T::env().block_timestamp())
+        .iter()
+        .try_fold(0 as Balance, |total, (_, amount)| {
+            total.checked_add(*amount)
+        })
+        .ok_or(StakingError::OverflowError)
+}
+
+/// Sends the withdrawal fee charged on the tokens the staker withdrew to the treasury, or keeps it
+/// for the emission pool when there is none.
+fn charge_withdrawal_fee<T>(
+    instance: &mut T,
+    staker: AccountId,
+    fee: Balance,
+) -> Result<(), StakingError>
+where
+    T: Storage<data::Data> + Internal,
+{
+    if fee > 0 {
+        match instance.data().treasury {
+            Some(treasury) => PSP22Ref::transfer(
+                &instance.data().staking_token,
+                treasury,
+                fee,
+                Vec::<u8>::new(),
+            )?,
+            // Fees are only charged without a treasury when the rewards are paid in the staking
+            // token, so they can fund them.
+            None => instance.data().keep_for_emission(fee)?,
+        }
+
+        instance.emit_withdrawal_fee_event(staker, fee);
+    }
+    Ok(())
+}
+
+/// Returns the position `id`, making sure it belongs to `staker`.
+fn owned_position<T>(
+    instance: &T,
+    staker: AccountId,
+    id: PositionId,
+) -> Result<Position, StakingError>
+where
+    T: Storage<data::Data>,
+{
+    let position = instance
+        .data()
+        .positions
+        .get(&id)
+        .ok_or(StakingError::PositionNotFound)?;
+
+    ensure!(position.owner == staker, StakingError::NotPositionOwner);
+    Ok(position)
+}
+
+/// Pays the rewards of the positions `ids` of the staker out of the reward reserve, or vests
+/// them if a vesting period is set, and mints the reputation they accrued.
+fn pay_rewards<T>(
+    instance: &mut T,
+    staker: AccountId,
+    ids: &[PositionId],
+) -> Result<(), StakingError>
+where
+    T: Storage<data::Data> + Internal,
+{
+    let rewards = take_rewards(instance, ids)?;
+
+    if rewards > 0 && instance.data().vesting_period > 0 {
+        vest_rewards(instance, staker, rewards)?;
+    } else if rewards > 0 {
+        PSP22Ref::transfer(
+            &instance.data().reward_token,
+            instance.data().reward_recipient(&staker),
+            rewards,
+            Vec::<u8>::new(),
+        )?;
+
+        instance.emit_reward_paid_event(staker, rewards);
+    }
+    instance.update_reputation(staker)?;
+
+    Ok(())
+}
+
+/// Pays the rewards of the staker in every extra reward token out of their reserves to their
+/// reward recipient.
+fn pay_extra_rewards<T>(instance: &mut T, staker: AccountId) -> Result<(), StakingError>
+where
+    T: Storage<data::Data> + Internal,
+{
+    // The extra rewards are kept by account, so they outlive the positions that earned them.
+    instance.update_reward_per_token()?;
+    instance.update_extra_rewards(staker)?;
+
+    let recipient = instance.data().reward_recipient(&staker);
+    for token in instance.data().reward_tokens.clone() {
+        let rewards = instance
+            .data()
+            .user_rewards
+            .get(&(token, staker))
+            .unwrap_or(0);
+        if rewards == 0 {
+            continue;
+        }
+
+        let mut state = instance
+            .data()
+            .reward_states
+            .get(&token)
+            .unwrap_or_default();
+        ensure!(
+            state.reward_reserve >= rewards,
+            StakingError::InsufficientRewardReserve
+        );
+        state.reward_reserve -= rewards;
+
+        instance.data().reward_states.insert(&token, &state);
+        instance.data().user_rewards.insert(&(token, staker), &0);
+
+        PSP22Ref::transfer(&token, recipient, rewards, Vec::<u8>::new())?;
+
+        instance.emit_extra_reward_paid_event(staker, token, rewards);
+    }
+
+    Ok(())
+}
+
+/// Adds `amount` rewards taken out of the reward reserve to the vesting ledger of the staker.
+fn vest_rewards<T>(instance: &mut T, staker: AccountId, amount: Balance) -> Result<(), StakingError>
+where
+    T: Storage<data::Data> + Internal,
+{
+    let period = instance.data().vesting_period;
+    let mut vesting = instance.data().vestings.get(&staker).unwrap_or_default();
+    vesting.add(amount, T::env().block_timestamp(), period)?;
+
+    instance.data().vestings.insert(&staker, &vesting);
+    instance.data().total_vesting = instance
+        .data()
+        .total_vesting
+        .checked_add(amount)
+        .ok_or(StakingError::OverflowError)?;
+
+    instance.emit_reward_vested_event(staker, amount, vesting.end);
+    Ok(())
+}
+
+/// Settles the rewards of the positions `ids`, and takes them out of the positions and of the
+/// reward reserve. The rewards must be paid out separately.
+///
+/// Returns the amount of rewards taken.
+pub fn take_rewards<T>(instance: &mut T, ids: &[PositionId]) -> Result<Balance, StakingError>
+where
+    T: Storage<data::Data> + Internal,
+{
+    let mut positions = Vec::new();
+    let mut rewards: Balance = 0;
+    for id in ids {
+        let position = instance.update_position_reward(*id)?;
+        rewards = rewards
+            .checked_add(position.rewards)
+            .ok_or(StakingError::OverflowError)?;
+        positions.push((*id, position));
+    }
+
+    if rewards > 0 {
+        ensure!(
+            instance.data().reward_reserve >= rewards,
+            StakingError::InsufficientRewardReserve
+        );
+
+        for (id, position) in positions {
+            instance.data().set_position(
+                id,
+                Position {
+                    rewards: 0,
+                    ..position
+                },
+            )?;
+        }
+        instance.data().reward_reserve -= rewards;
+    }
+
+    Ok(rewards)
+}
+
+/// Stakes `amount` tokens the contract holds, or is about to receive, for the staker. They top
+/// up the newest unlocked position of the staker without a multiplier, whose start time moves
+/// forward in proportion, or a new position if there is none. `fee_liable` tells whether the
+/// tokens are charged a fee when withdrawn, like new deposits.
+///
+/// Returns the id of the position credited.
+pub fn credit_stake<T>(
+    instance: &mut T,
+    staker: AccountId,
+    amount: Balance,
+    fee_liable: bool,
+) -> Result<PositionId, StakingError>
+where
+    T: Storage<data::Data> + Internal,
+{
+    let now = T::env().block_timestamp();
+
+    // Settle the reward per token and the reputation before the balances change.
+    instance.update_reward_per_token()?;
+    instance.update_extra_rewards(staker)?;
+    instance.checkpoint_reputation(staker)?;
+
+    let flexible = instance
+        .data()
+        .positions_of(&staker)
+        .into_iter()
+        .rev()
+        .find(|id| {
+            instance.data().positions.get(id).map_or(false, |position| {
+                position.multiplier == lockup::MULTIPLIER_SCALE && !position.is_locked(now)
+            })
+        });
+
+    let Some(id) = flexible else {
+        let mut position = Position::new(staker, amount, now, 0)?;
+        position.reward_per_token_paid = instance.data().reward_per_token_stored;
+        if !fee_liable {
+            position.fee_liable = 0;
+        }
+        return instance.data().open_position(position);
+    };
+
+    let mut position = instance.update_position_reward(id)?;
+    let total = position
+        .amount
+        .checked_add(amount)
+        .ok_or(StakingError::OverflowError)?;
+
+    // The start time is averaged by amount, so the tokens already staked keep their age.
+    position.start_time = math::mul_div(position.amount, position.start_time as u128, total)
+        .zip(math::mul_div(amount, now as u128, total))
+        .and_then(|(staked, credited)| staked.checked_add(credited))
+        .ok_or(StakingError::OverflowError)? as Timestamp;
+    position.amount = total;
+    if fee_liable {
+        let period = instance.data().withdrawal_fee_period;
+        position.add_fee_liable(amount, now, period)?;
+    }
+
+    instance.data().set_position(id, position)?;
+    Ok(id)
 }
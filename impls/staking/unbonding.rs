@@ -0,0 +1,17 @@
+use openbrush::traits::{Balance, Timestamp};
+
+/// How many unstake requests an account can have pending at once, which bounds the cost of
+/// completing them.
+pub const MAX_UNLOCKS: usize = 16;
+
+/// Tokens unstaked with `request_unstake`, which no longer earn rewards nor reputation and can
+/// be withdrawn from `release_time` on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, scale::Encode, scale::Decode)]
+#[cfg_attr(
+    feature = "std",
+    derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+)]
+pub struct Unlock {
+    pub amount: Balance,
+    pub release_time: Timestamp,
+}
@@ -0,0 +1,83 @@
+use crate::{impls::staking::math, traits::staking::StakingError};
+use openbrush::traits::{Balance, Timestamp};
+
+/// The basis points `early_exit_penalty` is expressed in.
+pub const PENALTY_SCALE: u16 = 10_000;
+
+/// Claimed rewards vesting linearly until `end`. Rewards claimed while some are still vesting
+/// are merged into a single schedule restarting at the claim.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, scale::Encode, scale::Decode)]
+#[cfg_attr(
+    feature = "std",
+    derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+)]
+pub struct Vesting {
+    /// Rewards vested and not released yet.
+    pub vested: Balance,
+    /// Rewards vesting linearly from `start` to `end`.
+    pub vesting: Balance,
+    /// When the current schedule started.
+    pub start: Timestamp,
+    /// When the current schedule ends.
+    pub end: Timestamp,
+}
+
+impl Vesting {
+    /// Returns the rewards that can be released at `now`.
+    pub fn vested_at(&self, now: Timestamp) -> Result<Balance, StakingError> {
+        let unlocked = if now >= self.end {
+            self.vesting
+        } else {
+            math::mul_div(
+                self.vesting,
+                now.saturating_sub(self.start) as u128,
+                (self.end - self.start) as u128,
+            )
+            .ok_or(StakingError::OverflowError)?
+        };
+
+        self.vested
+            .checked_add(unlocked)
+            .ok_or(StakingError::OverflowError)
+    }
+
+    /// Returns all the rewards of the ledger, vested or not.
+    pub fn total(&self) -> Result<Balance, StakingError> {
+        self.vested
+            .checked_add(self.vesting)
+            .ok_or(StakingError::OverflowError)
+    }
+
+    /// Adds `amount` rewards vesting over `period` from `now`, along with the rewards of the
+    /// current schedule that have not vested yet.
+    pub fn add(
+        &mut self,
+        amount: Balance,
+        now: Timestamp,
+        period: Timestamp,
+    ) -> Result<(), StakingError> {
+        let vested = self.vested_at(now)?;
+        let remaining = self.total()? - vested;
+
+        self.vested = vested;
+        self.vesting = remaining
+            .checked_add(amount)
+            .ok_or(StakingError::OverflowError)?;
+        self.start = now;
+        self.end = now.checked_add(period).ok_or(StakingError::OverflowError)?;
+        Ok(())
+    }
+
+    /// Takes the rewards vested at `now` out of the ledger, and returns them.
+    pub fn release(&mut self, now: Timestamp) -> Result<Balance, StakingError> {
+        let vested = self.vested_at(now)?;
+        let remaining = self.total()? - vested;
+
+        // The schedule restarts at `now` with what is left, which keeps releasing at the same
+        // pace until `end`.
+        self.vested = 0;
+        self.vesting = remaining;
+        self.start = now.min(self.end);
+        Ok(vested)
+    }
+}
@@ -0,0 +1,21 @@
+use openbrush::traits::AccountId;
+
+const STORAGE_KEY: u32 = openbrush::storage_unique_key!(Data);
+
+#[derive(Debug)]
+#[openbrush::upgradeable_storage(STORAGE_KEY)]
+pub struct Data {
+    /// The address of the staking token contract.
+    pub asset: AccountId,
+    /// The address of the staking contract the vault stakes in.
+    pub staking_contract: AccountId,
+}
+
+impl Default for Data {
+    fn default() -> Self {
+        Self {
+            asset: [0u8; 32].into(),
+            staking_contract: [0u8; 32].into(),
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use crate::impls::staking::math;
+use openbrush::traits::Balance;
+
+/// Shares and assets the conversions add to the vault, so that a donation to an empty or
+/// nearly empty vault mostly accrues to these virtual shares. Rounding the deposit of the next
+/// depositor down to nothing then costs the donor a thousand times that deposit.
+pub const VIRTUAL_OFFSET: Balance = 1_000;
+
+/// Returns the shares `assets` are worth when `total_supply` shares are backed by
+/// `total_assets`, rounded down. Shares are minted one for one while the vault is empty.
+///
+/// Returns `None` if the result overflows.
+pub fn to_shares(assets: Balance, total_supply: Balance, total_assets: Balance) -> Option<Balance> {
+    math::mul_div(
+        assets,
+        total_supply.checked_add(VIRTUAL_OFFSET)?,
+        total_assets.checked_add(VIRTUAL_OFFSET)?,
+    )
+}
+
+/// Returns the assets `shares` are worth when `total_supply` shares are backed by
+/// `total_assets`, rounded down.
+///
+/// Returns `None` if the result overflows.
+pub fn to_assets(shares: Balance, total_supply: Balance, total_assets: Balance) -> Option<Balance> {
+    math::mul_div(
+        shares,
+        total_assets.checked_add(VIRTUAL_OFFSET)?,
+        total_supply.checked_add(VIRTUAL_OFFSET)?,
+    )
+}
@@ -0,0 +1,123 @@
+use crate::{
+    impls::vault::{data, shares},
+    traits::{
+        staking::{StakingError, StakingRef},
+        vault::{Internal, *},
+    },
+};
+use ink::prelude::vec::Vec;
+use openbrush::{
+    contracts::{
+        psp22,
+        traits::psp22::{PSP22Ref, PSP22},
+    },
+    traits::{AccountId, Balance, Storage},
+};
+
+impl<T> Vault for T
+where
+    T: Storage<data::Data> + Storage<psp22::Data> + PSP22 + psp22::Internal + Internal,
+{
+    default fn deposit(&mut self, assets: Balance) -> Result<Balance, VaultError> {
+        if assets == 0 {
+            return Err(VaultError::ZeroAmount);
+        }
+
+        // A failed harvest only leaves the rewards pending, which the share price then ignores.
+        let _ = self.harvest();
+
+        let shares = shares::to_shares(assets, self.total_supply(), self.total_assets())
+            .ok_or(VaultError::OverflowError)?;
+        if shares == 0 {
+            return Err(VaultError::ZeroShares);
+        }
+
+        let owner = Self::env().caller();
+        let vault = Self::env().account_id();
+        let asset = self.data::<data::Data>().asset;
+        let staking_contract = self.data::<data::Data>().staking_contract;
+
+        PSP22Ref::transfer_from(&asset, owner, vault, assets, Vec::<u8>::new())?;
+
+        // Staking for the vault itself tops up its flexible position instead of opening a new
+        // one on every deposit.
+        PSP22Ref::approve(&asset, staking_contract, assets)?;
+        StakingRef::stake_for(&staking_contract, vault, assets)?;
+
+        self._mint_to(owner, shares)?;
+
+        self.emit_deposit_event(owner, assets, shares);
+        Ok(shares)
+    }
+
+    default fn redeem(&mut self, shares: Balance) -> Result<Balance, VaultError> {
+        if shares == 0 {
+            return Err(VaultError::ZeroAmount);
+        }
+
+        // Redemptions must not depend on compounding, so a failed harvest is skipped.
+        let _ = self.harvest();
+
+        let assets = shares::to_assets(shares, self.total_supply(), self.total_assets())
+            .ok_or(VaultError::OverflowError)?;
+
+        let owner = Self::env().caller();
+        let vault = Self::env().account_id();
+        let asset = self.data::<data::Data>().asset;
+        let staking_contract = self.data::<data::Data>().staking_contract;
+
+        self._burn_from(owner, shares)?;
+
+        // The staking contract may charge a withdrawal fee, which the redeemer bears, so only the
+        // tokens actually received are forwarded.
+        let assets = if assets > 0 {
+            let balance = PSP22Ref::balance_of(&asset, vault);
+            StakingRef::withdraw(&staking_contract, assets)?;
+            let received = PSP22Ref::balance_of(&asset, vault)
+                .checked_sub(balance)
+                .ok_or(VaultError::OverflowError)?;
+
+            PSP22Ref::transfer(&asset, owner, received, Vec::<u8>::new())?;
+            received
+        } else {
+            0
+        };
+
+        self.emit_redeem_event(owner, assets, shares);
+        Ok(assets)
+    }
+
+    default fn harvest(&mut self) -> Result<(), VaultError> {
+        match StakingRef::compound(&self.data::<data::Data>().staking_contract) {
+            Ok(()) | Err(StakingError::NoStakingRewards) => Ok(()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    default fn total_assets(&self) -> Balance {
+        let staking_contract = self.data::<data::Data>().staking_contract;
+        let vault = Self::env().account_id();
+
+        // Only the rewards a harvest could stake count, so the price does not move when they
+        // cannot be compounded.
+        StakingRef::balance_of(&staking_contract, vault).saturating_add(
+            StakingRef::compoundable_reward(&staking_contract, vault).unwrap_or_default(),
+        )
+    }
+
+    default fn convert_to_shares(&self, assets: Balance) -> Balance {
+        shares::to_shares(assets, self.total_supply(), self.total_assets()).unwrap_or(Balance::MAX)
+    }
+
+    default fn convert_to_assets(&self, shares: Balance) -> Balance {
+        shares::to_assets(shares, self.total_supply(), self.total_assets()).unwrap_or(Balance::MAX)
+    }
+
+    default fn asset(&self) -> AccountId {
+        self.data::<data::Data>().asset
+    }
+
+    default fn staking_contract(&self) -> AccountId {
+        self.data::<data::Data>().staking_contract
+    }
+}
@@ -1,2 +1,4 @@
+pub mod receipt;
+pub mod reputation;
 pub mod staking;
-pub mod reputation;
\ No newline at end of file
+pub mod vault;
@@ -0,0 +1,20 @@
+use openbrush::contracts::traits::psp22::*;
+use openbrush::traits::{AccountId, Balance};
+
+#[openbrush::wrapper]
+pub type ReceiptRef = dyn Receipt + PSP22;
+
+#[openbrush::trait_definition]
+pub trait Receipt: PSP22 {
+    /// Mints `amount` shares to the account, for the tokens it staked
+    #[ink(message)]
+    fn mint(&mut self, account: AccountId, amount: Balance) -> Result<(), PSP22Error>;
+
+    /// Burns `amount` shares of the account, for the tokens it unstaked
+    #[ink(message)]
+    fn burn(&mut self, account: AccountId, amount: Balance) -> Result<(), PSP22Error>;
+
+    /// Returns the address of the staking contract the shares are staked in
+    #[ink(message)]
+    fn staking_contract(&self) -> AccountId;
+}
@@ -6,16 +6,41 @@ pub type ReputationRef = dyn Reputation + PSP37 + AccessControl;
 
 #[openbrush::trait_definition]
 pub trait Reputation: PSP37 + AccessControl {
-    /// Update reputation of the account and mint tokens
+    /// Adds `delta` to the reputation of the account and mints the level tokens it reaches
+    #[ink(message)]
+    fn add_reputation(&mut self, account: AccountId, delta: u128) -> Result<(), PSP37Error>;
+
+    /// Overwrites the reputation of the account, minting or burning level tokens to match.
+    /// Reserved to managers for migrations.
     #[ink(message)]
     fn update_reputation(
         &mut self,
         staker: AccountId,
         new_reputation: u128,
     ) -> Result<(), PSP37Error>;
+
+    /// Returns the reputation of the account
+    #[ink(message)]
+    fn reputation_of(&self, account: AccountId) -> u128;
+
+    /// Returns the level of the account
+    #[ink(message)]
+    fn level_of(&self, account: AccountId) -> u32;
+
+    /// Returns the reputation the account needs to reach its next level, or `None` if no
+    /// higher level fits in a `u128`
+    #[ink(message)]
+    fn next_level_threshold(&self, account: AccountId) -> Option<u128>;
+
+    /// Returns how far the account is from its current level to the next one, in basis points
+    #[ink(message)]
+    fn progress_to_next_level(&self, account: AccountId) -> u16;
 }
 
 pub trait Internal {
     /// Returns the level of the reputation
     fn get_level(reputation: u128) -> u32;
+
+    /// Returns the reputation needed to reach the level, or `None` if it does not fit in a `u128`
+    fn level_threshold(level: u32) -> Option<u128>;
 }
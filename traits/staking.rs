@@ -1,16 +1,26 @@
+use crate::impls::staking::{
+    extra_rewards::RewardState,
+    position::{Position, PositionId},
+    unbonding::Unlock,
+    vesting::Vesting,
+};
+use ink::prelude::vec::Vec;
 use openbrush::{
-    contracts::traits::{psp22::PSP22Error, psp37::PSP37Error},
+    contracts::traits::{
+        access_control::AccessControlError, pausable::PausableError, psp22::PSP22Error,
+        psp37::PSP37Error,
+    },
     traits::{AccountId, Balance, Timestamp},
 };
 
-#[cfg(feature = "std")]
 #[openbrush::wrapper]
 pub type StakingRef = dyn Staking;
 
 #[openbrush::trait_definition]
 pub trait Staking {
-    /// Stakes the specified amount of tokens. The tokens are transferred from the caller's account.
-    /// The caller must have approved the contract to transfer the specified amount of tokens.
+    /// Stakes the specified amount of tokens in a new position. The tokens are transferred from
+    /// the caller's account. The caller must have approved the contract to transfer the specified
+    /// amount of tokens.
     ///
     /// `amount` - The amount of tokens to stake.
     ///
@@ -18,25 +28,231 @@ pub trait Staking {
     /// to transfer the specified amount of tokens.
     /// Returns `StakingError::InsufficientBalance` if the caller does not have enough tokens to
     /// stake.
+    /// Returns `StakingError::TooManyPositions` if the caller already holds the maximum number of
+    /// positions.
+    /// Returns `StakingError::BelowMinimum` if the amount is less than the minimum stake.
+    /// Returns `StakingError::CapExceeded` if the stake of the caller or the total stake would
+    /// exceed its cap.
     #[ink(message)]
     fn stake(&mut self, amount: Balance) -> Result<(), StakingError>;
 
-    /// Unstakes the specified amount of tokens. The tokens are transferred to the caller's account.
+    /// Stakes the specified amount of tokens in a new position locked for `duration`. Locked
+    /// tokens cannot be withdrawn before the lock expires and weigh more in the rewards sharing:
+    /// 1.5x when locked for six months, 2x for twelve months.
+    ///
+    /// `amount` - The amount of tokens to stake.
+    /// `duration` - How long the tokens are locked, in milliseconds.
+    #[ink(message)]
+    fn stake_locked(&mut self, amount: Balance, duration: Timestamp) -> Result<(), StakingError>;
+
+    /// Stakes the specified amount of tokens on behalf of `beneficiary`. The tokens are
+    /// transferred from the caller's account, while the beneficiary earns the rewards and the
+    /// reputation, and alone can withdraw them. They top up the newest unlocked position of the
+    /// beneficiary without a multiplier, or a new position if there is none, so that third
+    /// parties cannot use up the positions of the beneficiary.
+    ///
+    /// `beneficiary` - The address of the user to stake for.
+    /// `amount` - The amount of tokens to stake.
+    ///
+    /// Returns `StakingError::InsufficientAllowance` if the caller has not approved the contract
+    /// to transfer the specified amount of tokens.
+    /// Returns `StakingError::InsufficientBalance` if the caller does not have enough tokens to
+    /// stake.
+    /// Returns `StakingError::BelowMinimum` if the amount is less than the minimum stake.
+    /// Returns `StakingError::CapExceeded` if the stake of the beneficiary or the total stake
+    /// would exceed its cap.
+    #[ink(message)]
+    fn stake_for(&mut self, beneficiary: AccountId, amount: Balance) -> Result<(), StakingError>;
+
+    /// Unstakes the specified amount of tokens from the unlocked positions of the caller, newest
+    /// first. The tokens are transferred to the caller's account.
     ///
     /// `amount` - The amount of tokens to unstake.
     ///
     /// Returns `StakingError::InsufficientBalance` if the caller does not have enough tokens to
     /// unstake.
+    /// Returns `StakingError::StakeLocked` if the amount includes tokens that are still locked.
+    /// Returns `StakingError::UnbondingRequired` if an unbonding period is set, tokens must then
+    /// be unstaked with `request_unstake`.
     #[ink(message)]
     fn withdraw(&mut self, amount: Balance) -> Result<(), StakingError>;
 
-    /// Claims the staking rewards for the caller. The rewards are transferred to the caller's
-    /// account.
+    /// Unstakes the specified amount of tokens from a position of the caller. The tokens are
+    /// transferred to the caller's account.
+    ///
+    /// `id` - The id of the position.
+    /// `amount` - The amount of tokens to unstake.
+    ///
+    /// Returns `StakingError::InsufficientBalance` if the position does not hold enough tokens.
+    /// Returns `StakingError::StakeLocked` if the position is still locked.
+    /// Returns `StakingError::UnbondingRequired` if an unbonding period is set.
+    #[ink(message)]
+    fn withdraw_position(&mut self, id: PositionId, amount: Balance) -> Result<(), StakingError>;
+
+    /// Unstakes the specified amount of tokens from the unlocked positions of the caller, newest
+    /// first, into an unstake request. The tokens stop earning rewards and reputation right away,
+    /// and can be withdrawn with `complete_unstake` once the unbonding period has passed.
+    ///
+    /// `amount` - The amount of tokens to unstake.
+    ///
+    /// Returns `StakingError::InsufficientBalance` if the caller does not have enough tokens to
+    /// unstake.
+    /// Returns `StakingError::StakeLocked` if the amount includes tokens that are still locked.
+    /// Returns `StakingError::TooManyUnlocks` if the caller already has the maximum number of
+    /// pending requests.
+    #[ink(message)]
+    fn request_unstake(&mut self, amount: Balance) -> Result<(), StakingError>;
+
+    /// Transfers the tokens of the unstake requests of the caller that have passed the unbonding
+    /// period to the caller's account. While the contract is paused, every request can be
+    /// completed right away.
+    ///
+    /// Returns `StakingError::NothingToUnstake` if no request of the caller can be completed.
+    #[ink(message)]
+    fn complete_unstake(&mut self) -> Result<(), StakingError>;
+
+    /// Cancels all the pending unstake requests of the caller and stakes their tokens again in a
+    /// new position.
+    ///
+    /// Returns `StakingError::NothingToUnstake` if the caller has no pending request.
+    #[ink(message)]
+    fn cancel_unstake(&mut self) -> Result<(), StakingError>;
+
+    /// Returns the pending unstake requests of the specified user, oldest first.
+    ///
+    /// `staker` - The address of the user.
+    #[ink(message)]
+    fn pending_unlocks(&self, staker: AccountId) -> Vec<Unlock>;
+
+    /// Claims the staking rewards of all the positions of the caller. The rewards are transferred
+    /// to the reward recipient of the caller, the caller themselves by default, or vest first if
+    /// a vesting period is set.
     ///
-    /// Returns `StakingError::NoStakingRewards` if the caller has no staking rewards.
+    /// Returns `StakingError::InsufficientRewardReserve` if the funded rewards do not cover the
+    /// payout, staked tokens are never used to pay rewards.
     #[ink(message)]
     fn get_reward(&mut self) -> Result<(), StakingError>;
 
+    /// Claims the staking rewards of all the positions of the caller along with their rewards in
+    /// every extra reward token. The rewards are transferred to the reward recipient of the
+    /// caller.
+    ///
+    /// Returns `StakingError::InsufficientRewardReserve` if the funded rewards of a token do not
+    /// cover its payout.
+    #[ink(message)]
+    fn get_rewards(&mut self) -> Result<(), StakingError>;
+
+    /// Claims the staking rewards of all the positions of `staker` along with their rewards in
+    /// every extra reward token on their behalf, like `get_rewards`. The rewards are transferred
+    /// to the reward recipient of the staker.
+    ///
+    /// `staker` - The address of the user.
+    ///
+    /// Returns `StakingError::NotOperator` if the caller is neither the staker nor their
+    /// operator.
+    /// Returns `StakingError::InsufficientRewardReserve` if the funded rewards of a token do not
+    /// cover its payout.
+    #[ink(message)]
+    fn claim_for(&mut self, staker: AccountId) -> Result<(), StakingError>;
+
+    /// Sets the address the rewards of the caller are paid to, for instance a cold wallet or a
+    /// vault. `None` pays them to the caller again.
+    ///
+    /// `recipient` - The address to pay the rewards to.
+    #[ink(message)]
+    fn set_reward_recipient(&mut self, recipient: Option<AccountId>) -> Result<(), StakingError>;
+
+    /// Returns the address the rewards of the specified user are paid to, the user themselves
+    /// unless they set a reward recipient.
+    ///
+    /// `staker` - The address of the user.
+    #[ink(message)]
+    fn reward_recipient(&self, staker: AccountId) -> AccountId;
+
+    /// Authorizes `operator` to claim the rewards of the caller with `claim_for`, replacing the
+    /// previous operator. `None` revokes the current operator.
+    ///
+    /// `operator` - The address of the operator.
+    #[ink(message)]
+    fn set_operator(&mut self, operator: Option<AccountId>) -> Result<(), StakingError>;
+
+    /// Returns the operator allowed to claim the rewards of the specified user, if any.
+    ///
+    /// `staker` - The address of the user.
+    #[ink(message)]
+    fn operator_of(&self, staker: AccountId) -> Option<AccountId>;
+
+    /// Transfers the vested rewards of the caller to their reward recipient.
+    ///
+    /// Returns `StakingError::NothingVested` if no reward of the caller has vested yet.
+    #[ink(message)]
+    fn release_vested(&mut self) -> Result<(), StakingError>;
+
+    /// Transfers all the vesting rewards of the caller to their reward recipient right away,
+    /// minus the early exit penalty on the rewards that have not vested yet. The penalty is sent
+    /// to the treasury, or kept for the emission pool until the next funding.
+    ///
+    /// Returns `StakingError::EarlyExitDisabled` if no early exit penalty is set.
+    /// Returns `StakingError::NothingVested` if the caller has no vesting rewards.
+    #[ink(message)]
+    fn release_vested_early(&mut self) -> Result<(), StakingError>;
+
+    /// Returns the rewards of the specified user that have vested and can be released at the
+    /// current block.
+    ///
+    /// `account` - The address of the user.
+    #[ink(message)]
+    fn vested_amount(&self, account: AccountId) -> Result<Balance, StakingError>;
+
+    /// Returns the vesting ledger of the specified user, if they have vesting rewards.
+    ///
+    /// `account` - The address of the user.
+    #[ink(message)]
+    fn vesting_of(&self, account: AccountId) -> Option<Vesting>;
+
+    /// Stakes the staking rewards of all the positions of the caller, without transferring them.
+    /// They top up the newest unlocked position of the caller without a multiplier, or a new
+    /// position if there is none.
+    ///
+    /// Returns `StakingError::RewardNotStakable` if the rewards are paid in another token than the
+    /// staking token.
+    /// Returns `StakingError::RewardsVesting` if a vesting period is set.
+    /// Returns `StakingError::NoStakingRewards` if the caller has no rewards to compound.
+    /// Returns `StakingError::InsufficientRewardReserve` if the funded rewards do not cover them.
+    #[ink(message)]
+    fn compound(&mut self) -> Result<(), StakingError>;
+
+    /// Claims the staking rewards of a position of the caller. The rewards are transferred to the
+    /// reward recipient of the caller.
+    ///
+    /// `id` - The id of the position.
+    #[ink(message)]
+    fn claim_position(&mut self, id: PositionId) -> Result<(), StakingError>;
+
+    /// Unstakes all the unlocked tokens of the caller and claims the staking rewards of all their
+    /// positions, in a single transfer when the rewards are paid in the staking token to the
+    /// caller, and mints the reputation they accrued. Locked positions stay staked and keep
+    /// earning. Their rewards in the extra reward tokens stay claimable with `get_rewards`. The
+    /// rewards vest first if a vesting period is set.
+    ///
+    /// Returns `StakingError::InsufficientBalance` if the caller has neither unlocked tokens nor
+    /// rewards.
+    /// Returns `StakingError::UnbondingRequired` if an unbonding period is set.
+    #[ink(message)]
+    fn exit(&mut self) -> Result<(), StakingError>;
+
+    /// Unstakes all the tokens of the caller without settling anything, forfeiting the pending
+    /// rewards of the positions it empties and the reputation and extra rewards accrued on their
+    /// tokens since they were last settled. Unlike the other messages, it keeps working while
+    /// the contract is paused. Locked positions are only emptied while the contract is paused.
+    /// No withdrawal fee is charged and the receipt shares are not burned.
+    ///
+    /// Returns `StakingError::InsufficientBalance` if the caller has no tokens staked.
+    /// Returns `StakingError::UnbondingRequired` if an unbonding period is set and the contract
+    /// is not paused.
+    #[ink(message)]
+    fn emergency_withdraw(&mut self) -> Result<(), StakingError>;
+
     /// Returns the amount of tokens staked by the specified user. If the user has not staked any
     /// tokens, this method returns `0`.
     ///
@@ -47,20 +263,177 @@ pub trait Staking {
     /// Returns the total amount of tokens staked.
     #[ink(message)]
     fn total_staked(&self) -> Balance;
+
+    /// Returns the positions of the specified user with their ids, oldest first.
+    ///
+    /// `staker` - The address of the user.
+    #[ink(message)]
+    fn positions_of(&self, staker: AccountId) -> Vec<(PositionId, Position)>;
+
+    /// Moves `amount` staked tokens from the unlocked positions of `from`, newest first, to the
+    /// newest unlocked position of `to` without a multiplier, or a new position if there is none.
+    /// The rewards and reputation of both are settled beforehand. Reserved to the receipt token,
+    /// which calls it when shares are transferred.
+    ///
+    /// `from` - The address of the user the stake moves from.
+    /// `to` - The address of the user the stake moves to.
+    /// `amount` - The amount of staked tokens to move.
+    ///
+    /// Returns `StakingError::NotReceiptToken` if the caller is not the receipt token.
+    /// Returns `StakingError::StakeLocked` if the amount includes tokens that are still locked.
+    /// Returns `StakingError::CapExceeded` if the stake of `to` would exceed its cap.
+    #[ink(message)]
+    fn transfer_stake(
+        &mut self,
+        from: AccountId,
+        to: AccountId,
+        amount: Balance,
+    ) -> Result<(), StakingError>;
+
+    /// Releases the expired lock of a position, so that its tokens stop weighing more in the
+    /// rewards sharing. Anyone can call it.
+    ///
+    /// `id` - The id of the position.
+    #[ink(message)]
+    fn release_lock(&mut self, id: PositionId) -> Result<(), StakingError>;
+
+    /// Returns the rewards the specified user could claim at the current block.
+    ///
+    /// `account` - The address of the user.
+    #[ink(message)]
+    fn pending_reward(&self, account: AccountId) -> Result<Balance, StakingError>;
+
+    /// Returns the rewards `compound` would stake for the specified user at the current block.
+    /// Zero when they cannot be compounded: the rewards are paid in another token or vest, the
+    /// contract is paused or the reward reserve falls short.
+    ///
+    /// `account` - The address of the user.
+    #[ink(message)]
+    fn compoundable_reward(&self, account: AccountId) -> Result<Balance, StakingError>;
+
+    /// Returns the rewards the specified user could claim with `get_rewards` at the current block,
+    /// by token. The reward token comes first, followed by the extra reward tokens.
+    ///
+    /// `account` - The address of the user.
+    #[ink(message)]
+    fn pending_rewards(
+        &self,
+        account: AccountId,
+    ) -> Result<Vec<(AccountId, Balance)>, StakingError>;
+
+    /// Returns the addresses of the extra reward tokens, in the order they were added.
+    #[ink(message)]
+    fn reward_tokens(&self) -> Vec<AccountId>;
+
+    /// Returns the distribution of an extra reward token, or `None` if it was never added.
+    ///
+    /// `token` - The address of the extra reward token.
+    #[ink(message)]
+    fn reward_state(&self, token: AccountId) -> Option<RewardState>;
+
+    /// Returns the rewards accumulated per staked token since the deployment, scaled by 1e18,
+    /// at the current block.
+    #[ink(message)]
+    fn reward_per_token(&self) -> Result<Balance, StakingError>;
+
+    /// Returns the amount of reward tokens released per millisecond at the current block,
+    /// shared by all the stakers.
+    #[ink(message)]
+    fn current_reward_rate(&self) -> Result<Balance, StakingError>;
+
+    /// Returns the timestamp the reward per token was last stored at.
+    #[ink(message)]
+    fn last_update_time(&self) -> Timestamp;
 }
 
 pub trait Internal {
     /// Returns the staking reward per token.
     fn reward_per_token(&self) -> Result<Balance, StakingError>;
 
-    /// Updates the staking rewards for the specified user.
+    /// Stores the reward per token accumulated up to the current block.
+    fn update_reward_per_token(&mut self) -> Result<(), StakingError>;
+
+    /// Updates the staking rewards of all the positions of the specified user.
     fn update_reward(&mut self, account: AccountId) -> Result<(), StakingError>;
 
+    /// Updates the staking rewards of the position, and returns it.
+    fn update_position_reward(&mut self, id: PositionId) -> Result<Position, StakingError>;
+
+    /// Returns the rewards earned by all the positions of the specified user.
     fn earned(&self, account: AccountId) -> Result<Balance, StakingError>;
 
+    /// Returns the rewards earned by the position.
+    fn position_earned(&self, position: &Position) -> Result<Balance, StakingError>;
+
     fn last_time_reward_applicable(&self) -> Result<Timestamp, StakingError>;
 
+    /// Settles the rewards of the specified user in every extra reward token at their current
+    /// weight. Must be called before every change of that weight, once the reward per token is
+    /// updated.
+    fn update_extra_rewards(&mut self, account: AccountId) -> Result<(), StakingError>;
+
+    /// Returns the rewards earned by the specified user in an extra reward token.
+    fn extra_earned(&self, token: AccountId, account: AccountId) -> Result<Balance, StakingError>;
+
+    /// Accrues the reputation earned by the current balance of the staker since its last
+    /// checkpoint. Must be called before every change of that balance.
+    fn checkpoint_reputation(&mut self, staker: AccountId) -> Result<(), StakingError>;
+
+    /// Checkpoints the reputation of the staker and mints the whole points accrued so far.
     fn update_reputation(&mut self, staker: AccountId) -> Result<(), StakingError>;
+
+    /// Mints `amount` receipt shares to the staker, if a receipt token is set.
+    fn mint_receipt(&mut self, staker: AccountId, amount: Balance) -> Result<(), StakingError>;
+
+    /// Burns `amount` receipt shares of the staker, if a receipt token is set.
+    fn burn_receipt(&mut self, staker: AccountId, amount: Balance) -> Result<(), StakingError>;
+
+    /// Emits an event when `amount` tokens are staked for `staker` in the position `id`.
+    fn emit_staked_event(&self, staker: AccountId, id: PositionId, amount: Balance);
+
+    /// Emits an event when `amount` tokens staked in the position `id` are returned to `staker`.
+    fn emit_withdrawn_event(&self, staker: AccountId, id: PositionId, amount: Balance);
+
+    /// Emits an event when `reward` tokens are paid out to `staker`.
+    fn emit_reward_paid_event(&self, staker: AccountId, reward: Balance);
+
+    /// Emits an event when `reward` tokens of the extra reward token `token` are paid out to
+    /// `staker`.
+    fn emit_extra_reward_paid_event(&self, staker: AccountId, token: AccountId, reward: Balance);
+
+    /// Emits an event when `staker` sets the address their rewards are paid to.
+    fn emit_reward_recipient_changed_event(&self, staker: AccountId, recipient: Option<AccountId>);
+
+    /// Emits an event when `staker` authorizes or revokes the operator claiming their rewards.
+    fn emit_operator_changed_event(&self, staker: AccountId, operator: Option<AccountId>);
+
+    /// Emits an event when `amount` claimed rewards of `staker` start vesting, until `end` along
+    /// with the rewards already vesting.
+    fn emit_reward_vested_event(&self, staker: AccountId, amount: Balance, end: Timestamp);
+
+    /// Emits an event when `staker` forfeits `penalty` rewards by releasing them early.
+    fn emit_early_exit_penalty_event(&self, staker: AccountId, penalty: Balance);
+
+    /// Emits an event when `fee` tokens withdrawn by `staker` are charged as a withdrawal fee.
+    fn emit_withdrawal_fee_event(&self, staker: AccountId, fee: Balance);
+
+    /// Emits an event when `staker` requests to unstake `amount` tokens, which can be withdrawn
+    /// from `release_time` on.
+    fn emit_unstake_requested_event(
+        &self,
+        staker: AccountId,
+        amount: Balance,
+        release_time: Timestamp,
+    );
+
+    /// Emits an event when the `amount` tokens of completed unstake requests are transferred to
+    /// `staker`.
+    fn emit_unstake_completed_event(&self, staker: AccountId, amount: Balance);
+
+    /// Adds `amount` to the emission pool. The halving schedule restarts at the current block
+    /// with the new amount, whatever the previous schedule had not released yet and the rewards
+    /// kept for the emission pool since.
+    fn schedule_rewards(&mut self, amount: Balance) -> Result<(), StakingError>;
 }
 
 // Define an enum for the error codes that can be returned by the Staking trait.
@@ -83,6 +456,52 @@ pub enum StakingError {
     OverflowError,
     /// Division by zero error
     DivideByZero,
+    /// The reward reserve does not cover the rewards to pay.
+    InsufficientRewardReserve,
+    /// Access control error
+    AccessControlError(AccessControlError),
+    /// Pausable error
+    PausableError(PausableError),
+    /// The tokens to withdraw are still locked.
+    StakeLocked,
+    /// The position does not exist.
+    PositionNotFound,
+    /// The position belongs to another account.
+    NotPositionOwner,
+    /// The account already holds the maximum number of positions.
+    TooManyPositions,
+    /// Tokens must be unstaked with `request_unstake` while an unbonding period is set.
+    UnbondingRequired,
+    /// The account already has the maximum number of pending unstake requests.
+    TooManyUnlocks,
+    /// The account has no unstake request to complete or cancel.
+    NothingToUnstake,
+    /// The rewards are paid in another token than the staking token, so they cannot be staked.
+    RewardNotStakable,
+    /// The token is already distributed as a reward.
+    RewardTokenAlreadyAdded,
+    /// The token is not an extra reward token.
+    UnknownRewardToken,
+    /// The maximum number of extra reward tokens is already distributed.
+    TooManyRewardTokens,
+    /// The caller is neither the staker nor their operator.
+    NotOperator,
+    /// The caller is not the receipt token.
+    NotReceiptToken,
+    /// The receipt token cannot change while tokens are staked or unbonding.
+    StakeOutstanding,
+    /// The account has no rewards to release.
+    NothingVested,
+    /// Rewards cannot be released before they vest.
+    EarlyExitDisabled,
+    /// The value exceeds 10_000 basis points.
+    BasisPointsOutOfRange,
+    /// The stake would exceed the cap of the account or the total cap.
+    CapExceeded,
+    /// The amount is less than the minimum stake.
+    BelowMinimum,
+    /// Rewards vest while a vesting period is set, so they cannot be compounded.
+    RewardsVesting,
 }
 
 impl From<PSP22Error> for StakingError {
@@ -91,6 +510,18 @@ impl From<PSP22Error> for StakingError {
     }
 }
 
+impl From<AccessControlError> for StakingError {
+    fn from(error: AccessControlError) -> Self {
+        Self::AccessControlError(error)
+    }
+}
+
+impl From<PausableError> for StakingError {
+    fn from(error: PausableError) -> Self {
+        Self::PausableError(error)
+    }
+}
+
 impl From<PSP37Error> for StakingError {
     fn from(error: PSP37Error) -> Self {
         Self::PSP37Error(error.into())
@@ -0,0 +1,106 @@
+use crate::traits::staking::StakingError;
+use openbrush::{
+    contracts::traits::psp22::{PSP22Error, *},
+    traits::{AccountId, Balance},
+};
+
+#[openbrush::wrapper]
+pub type VaultRef = dyn Vault + PSP22;
+
+#[openbrush::trait_definition]
+pub trait Vault: PSP22 {
+    /// Transfers `assets` staking tokens from the caller, stakes them in the staking contract
+    /// and mints vault shares to the caller. The caller must have approved the vault to
+    /// transfer the specified amount of tokens.
+    ///
+    /// `assets` - The amount of staking tokens to deposit.
+    ///
+    /// Returns the amount of shares minted.
+    /// Returns `VaultError::ZeroAmount` if `assets` is zero.
+    /// Returns `VaultError::ZeroShares` if `assets` is worth less than one share.
+    #[ink(message)]
+    fn deposit(&mut self, assets: Balance) -> Result<Balance, VaultError>;
+
+    /// Burns `shares` vault shares of the caller, unstakes the staking tokens they are worth
+    /// and transfers them to the caller, minus the withdrawal fee the staking contract charges.
+    ///
+    /// `shares` - The amount of shares to redeem.
+    ///
+    /// Returns the amount of staking tokens transferred.
+    /// Returns `VaultError::ZeroAmount` if `shares` is zero.
+    /// Returns `VaultError::StakingError(StakingError::UnbondingRequired)` if the staking
+    /// contract has an unbonding period.
+    #[ink(message)]
+    fn redeem(&mut self, shares: Balance) -> Result<Balance, VaultError>;
+
+    /// Stakes the rewards earned by the vault, which raises the value of every share. Anyone can
+    /// call it, and deposits and redemptions call it first, going ahead without the rewards when
+    /// it fails.
+    ///
+    /// Returns `VaultError::StakingError(StakingError::RewardNotStakable)` if the staking
+    /// contract pays its rewards in another token than the staking token.
+    /// Returns `VaultError::StakingError(StakingError::RewardsVesting)` if the staking contract
+    /// vests its rewards.
+    #[ink(message)]
+    fn harvest(&mut self) -> Result<(), VaultError>;
+
+    /// Returns the staking tokens the vault manages: the tokens it staked and the pending
+    /// rewards a harvest could stake.
+    #[ink(message)]
+    fn total_assets(&self) -> Balance;
+
+    /// Returns the shares `assets` staking tokens are worth, rounded down.
+    ///
+    /// `assets` - The amount of staking tokens.
+    #[ink(message)]
+    fn convert_to_shares(&self, assets: Balance) -> Balance;
+
+    /// Returns the staking tokens `shares` shares are worth, rounded down.
+    ///
+    /// `shares` - The amount of shares.
+    #[ink(message)]
+    fn convert_to_assets(&self, shares: Balance) -> Balance;
+
+    /// Returns the address of the staking token contract.
+    #[ink(message)]
+    fn asset(&self) -> AccountId;
+
+    /// Returns the address of the staking contract the vault stakes in.
+    #[ink(message)]
+    fn staking_contract(&self) -> AccountId;
+}
+
+pub trait Internal {
+    /// Emits an event when `owner` deposits `assets` staking tokens for `shares` shares.
+    fn emit_deposit_event(&self, owner: AccountId, assets: Balance, shares: Balance);
+
+    /// Emits an event when `owner` redeems `shares` shares for `assets` staking tokens.
+    fn emit_redeem_event(&self, owner: AccountId, assets: Balance, shares: Balance);
+}
+
+#[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+pub enum VaultError {
+    /// The amount is zero.
+    ZeroAmount,
+    /// The deposit is worth less than one share.
+    ZeroShares,
+    /// Overflow error
+    OverflowError,
+    /// Staking error
+    StakingError(StakingError),
+    /// PSP22 error
+    PSP22Error(PSP22Error),
+}
+
+impl From<StakingError> for VaultError {
+    fn from(error: StakingError) -> Self {
+        Self::StakingError(error)
+    }
+}
+
+impl From<PSP22Error> for VaultError {
+    fn from(error: PSP22Error) -> Self {
+        Self::PSP22Error(error)
+    }
+}